@@ -0,0 +1,27 @@
+// Sampling block textures, which come either packed into one atlas or as the layers of a texture array (see
+// rendering::texture::TextureBackend). TEXTURE_ARRAY is defined by the renderer when they're a texture array.
+
+#ifdef TEXTURE_ARRAY
+uniform sampler2DArray blockAtlas; // One block texture per layer
+#else
+uniform sampler2D blockAtlas; // Texture atlas containing multiple cube textures
+#endif
+
+// texCoord counts in blocks, tile is the texture's rectangle in the atlas and layer its layer in the texture array
+vec4 sampleBlockTexture(vec2 texCoord, vec4 tile, float layer) {
+    // texture coordinates count in blocks, so wrap them to repeat the tile once per block on merged faces
+    vec2 tileCoord = fract(texCoord);
+
+#ifdef TEXTURE_ARRAY
+    // each layer is a whole texture, so the gradients need no scaling
+    return textureGrad(blockAtlas, vec3(tileCoord, layer), dFdx(texCoord), dFdy(texCoord));
+#else
+    // the mipmap level comes from the unwrapped coordinates, fract jumps at every block edge which would otherwise
+    // pick the smallest level there and leave seams
+    vec2 atlasGradX = dFdx(texCoord) * tile.zw;
+    vec2 atlasGradY = dFdy(texCoord) * tile.zw;
+
+    // Sample the face's region of the atlas
+    return textureGrad(blockAtlas, tileCoord * tile.zw + tile.xy, atlasGradX, atlasGradY);
+#endif
+}
@@ -0,0 +1,6 @@
+// Values that are the same for every program during a frame, filled from rendering::uniform_buffer::FrameUniforms
+
+layout (std140) uniform Frame {
+    mat4 projection;
+    mat4 view;
+};
@@ -0,0 +1,18 @@
+#version 330 core
+
+#include "block_texture.glsl"
+
+out vec4 fragColour;
+
+in vec2 texCoord; // Texture coordinates for the face, counting in blocks
+flat in vec4 fragTile; // x, y, width and height of the face's texture in the atlas, all from 0 to 1
+flat in float fragLayer; // layer of the face's texture, when block textures are a texture array
+
+void main() {
+    fragColour = sampleBlockTexture(texCoord, fragTile, fragLayer);
+
+    // cut-out textures like plants are either there or not, so there's nothing to sort or blend
+    if (fragColour.a < 0.5) {
+        discard;
+    }
+}
@@ -0,0 +1,40 @@
+#version 330 core
+
+#include "frame.glsl"
+
+layout (location = 0) in vec3 position;
+layout (location = 1) in vec2 texture;
+layout (location = 2) in vec3 normal;
+layout (location = 3) in vec4 tile; // the face's rectangle in the atlas, when drawing chunk meshes
+layout (location = 4) in vec3 offset; // where an instanced cube goes, zero when drawing chunk meshes
+layout (location = 5) in vec4 faceTiles[6]; // the rectangles of an instanced cube's faces, takes locations 5 to 10
+layout (location = 11) in float layer; // the face's layer in the texture array, when drawing chunk meshes
+layout (location = 12) in vec3 faceLayers[2]; // the layers of an instanced cube's faces, three per location
+
+out vec2 texCoord;
+flat out vec4 fragTile;
+flat out float fragLayer;
+
+uniform mat4 model;
+uniform bool instanced;
+
+void main() {
+    gl_Position = projection * view * model * vec4(position + offset, 1.0);
+    texCoord = vec2(texture.x, texture.y);
+
+    if (instanced) {
+        // Cube::VERTICES has six vertices per face, in the same order as the tiles
+        int face = gl_VertexID / 6;
+        fragTile = faceTiles[0];
+        fragLayer = faceLayers[0].x;
+        for (int i = 1; i < 6; i++) {
+            if (face == i) {
+                fragTile = faceTiles[i];
+                fragLayer = faceLayers[i / 3][i % 3];
+            }
+        }
+    } else {
+        fragTile = tile;
+        fragLayer = layer;
+    }
+}
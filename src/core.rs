@@ -1,4 +1,6 @@
 pub mod lib;
 pub mod camera;
+pub mod assets;
+pub mod config;
 pub mod game;
 pub mod game_window;
\ No newline at end of file
@@ -0,0 +1,380 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use zip::ZipArchive;
+use crate::game_specs::BUILT_IN_ASSETS;
+
+// Textures, block data and shaders are looked up by name, e.g. "textures/blocks/stone.png" or "shaders/shader.vert",
+// rather than by path. Names are found in the built-in assets (BUILT_IN_ASSETS) and in any resource packs given with
+// --resource-pack, which are directories or zip files laid out the same way. A pack only needs the files it changes,
+// the rest still come from the packs below it, and packs given later are searched first.
+//
+// With --watch-assets an AssetWatcher checks the packs' files and their modification times so the game can reload
+// them as they're edited, added or removed.
+
+#[derive(Debug)]
+pub enum AssetError {
+    // no pack has an asset with this name
+    NotFound(String),
+    // names have to be relative paths that stay inside the packs
+    InvalidName(String),
+    // a pack or one of its files couldn't be read
+    Io { path : PathBuf, error : io::Error },
+    // an asset or pack was read but its contents can't be used
+    Invalid { name : String, message : String },
+}
+
+impl AssetError {
+    pub fn invalid<E : Display>(name : &str, error : E) -> AssetError {
+        AssetError::Invalid { name: name.to_string(), message: error.to_string() }
+    }
+
+    fn io(path : &Path, error : io::Error) -> AssetError {
+        AssetError::Io { path: path.to_path_buf(), error }
+    }
+}
+
+impl Display for AssetError {
+    fn fmt(&self, f : &mut Formatter) -> fmt::Result {
+        match self {
+            AssetError::NotFound(name) => write!(f, "no asset called {}", name),
+            AssetError::InvalidName(name) => write!(f, "invalid asset name {}", name),
+            AssetError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            AssetError::Invalid { name, message } => write!(f, "{}: {}", name, message),
+        }
+    }
+}
+
+impl Error for AssetError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AssetError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+pub struct Assets {
+    // searched in order, so the last pack given comes first and the built-in assets come last
+    packs : Vec<AssetPack>,
+}
+
+enum AssetPack {
+    Directory(PathBuf),
+    // the archive is opened again for each read, only the names of its files are kept
+    Zip { path : PathBuf, names : BTreeSet<String> },
+}
+
+impl Assets {
+    // only the assets that come with the game
+    pub fn built_in() -> Assets {
+        Assets {
+            packs: vec![AssetPack::Directory(PathBuf::from(BUILT_IN_ASSETS))],
+        }
+    }
+
+    // the built-in assets with resource packs over them, each pack overriding the ones before it
+    pub fn with_packs(packs : &[PathBuf]) -> Result<Assets, AssetError> {
+        let mut assets = Assets::built_in();
+
+        for pack in packs {
+            assets.add_pack(pack)?;
+        }
+
+        Ok(assets)
+    }
+
+    // put a directory or zip file over every pack added so far
+    pub fn add_pack(&mut self, path : &Path) -> Result<(), AssetError> {
+        let pack = if path.is_dir() {
+            AssetPack::Directory(path.to_path_buf())
+        } else {
+            let archive = open_zip(path)?;
+            let names = archive.file_names().filter(|name| !name.ends_with('/')).map(str::to_string).collect();
+
+            AssetPack::Zip { path: path.to_path_buf(), names }
+        };
+
+        self.packs.insert(0, pack);
+        Ok(())
+    }
+
+    // the contents of an asset from the first pack that has it
+    pub fn read(&self, name : &str) -> Result<Vec<u8>, AssetError> {
+        check_name(name)?;
+
+        for pack in &self.packs {
+            if let Some(data) = pack.read(name)? {
+                return Ok(data);
+            }
+        }
+
+        Err(AssetError::NotFound(name.to_string()))
+    }
+
+    pub fn read_to_string(&self, name : &str) -> Result<String, AssetError> {
+        String::from_utf8(self.read(name)?).map_err(|e| AssetError::invalid(name, e))
+    }
+
+    // the names of every asset directly inside a directory, from all of the packs
+    pub fn list(&self, directory : &str) -> Result<Vec<String>, AssetError> {
+        check_name(directory)?;
+
+        let mut names = BTreeSet::new();
+
+        for pack in &self.packs {
+            pack.list(directory, &mut names)?;
+        }
+
+        Ok(names.into_iter().collect())
+    }
+
+    // the path and modification time of every file in the packs, a zip file counts as one file
+    pub fn modification_times(&self) -> Result<BTreeMap<PathBuf, SystemTime>, AssetError> {
+        let mut times = BTreeMap::new();
+
+        for pack in &self.packs {
+            match pack {
+                AssetPack::Directory(root) => modification_times(root, &mut times).map_err(|e| AssetError::io(root, e))?,
+                AssetPack::Zip { path, .. } => {
+                    let modified = fs::metadata(path).and_then(|metadata| metadata.modified());
+                    times.insert(path.clone(), modified.map_err(|e| AssetError::io(path, e))?);
+                }
+            }
+        }
+
+        Ok(times)
+    }
+}
+
+// notices when any file in the packs changes, appears or goes away
+pub struct AssetWatcher {
+    // compared as a whole, so a file restored with an older time still counts as a change
+    modification_times : BTreeMap<PathBuf, SystemTime>,
+    interval : Duration,
+    last_check : Instant,
+}
+
+impl AssetWatcher {
+    // the packs are checked at most once per interval
+    pub fn new(assets : &Assets, interval : Duration) -> AssetWatcher {
+        AssetWatcher {
+            modification_times: assets.modification_times().unwrap_or_default(),
+            interval,
+            last_check: Instant::now(),
+        }
+    }
+
+    // true once for each change since the last time it returned true
+    pub fn poll(&mut self, assets : &Assets) -> bool {
+        if self.last_check.elapsed() < self.interval {
+            return false;
+        }
+        self.last_check = Instant::now();
+
+        // a file that's halfway through being saved can't be read, it's picked up on a later check
+        match assets.modification_times() {
+            Ok(times) if times != self.modification_times => {
+                self.modification_times = times;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl AssetPack {
+    // None if the pack doesn't have the asset
+    fn read(&self, name : &str) -> Result<Option<Vec<u8>>, AssetError> {
+        match self {
+            AssetPack::Directory(root) => {
+                let path = root.join(name);
+
+                match fs::read(&path) {
+                    Ok(data) => Ok(Some(data)),
+                    Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+                    Err(error) => Err(AssetError::io(&path, error)),
+                }
+            }
+            AssetPack::Zip { path, names } => {
+                if !names.contains(name) {
+                    return Ok(None);
+                }
+
+                let mut archive = open_zip(path)?;
+                let mut file = archive.by_name(name).map_err(|e| AssetError::invalid(&path.display().to_string(), e))?;
+                let mut data = Vec::new();
+                file.read_to_end(&mut data).map_err(|e| AssetError::io(path, e))?;
+
+                Ok(Some(data))
+            }
+        }
+    }
+
+    fn list(&self, directory : &str, names : &mut BTreeSet<String>) -> Result<(), AssetError> {
+        match self {
+            AssetPack::Directory(root) => {
+                let path = root.join(directory);
+                let entries = match fs::read_dir(&path) {
+                    Ok(entries) => entries,
+                    Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+                    Err(error) => return Err(AssetError::io(&path, error)),
+                };
+
+                for entry in entries {
+                    let entry = entry.map_err(|e| AssetError::io(&path, e))?;
+
+                    if entry.path().is_file() {
+                        if let Some(file_name) = entry.file_name().to_str() {
+                            names.insert(format!("{}/{}", directory, file_name));
+                        }
+                    }
+                }
+            }
+            AssetPack::Zip { names: files, .. } => {
+                let prefix = format!("{}/", directory);
+
+                names.extend(files
+                    .iter()
+                    .filter(|name| name.strip_prefix(&prefix).is_some_and(|rest| !rest.contains('/')))
+                    .cloned());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// add every file under a directory to times
+fn modification_times(directory : &Path, times : &mut BTreeMap<PathBuf, SystemTime>) -> io::Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+
+        if entry.file_type()?.is_dir() {
+            modification_times(&entry.path(), times)?;
+        } else {
+            times.insert(entry.path(), entry.metadata()?.modified()?);
+        }
+    }
+
+    Ok(())
+}
+
+// names are relative and can't climb out of a pack
+fn check_name(name : &str) -> Result<(), AssetError> {
+    if Path::new(name).components().all(|component| matches!(component, Component::Normal(_))) {
+        Ok(())
+    } else {
+        Err(AssetError::InvalidName(name.to_string()))
+    }
+}
+
+fn open_zip(path : &Path) -> Result<ZipArchive<File>, AssetError> {
+    let file = File::open(path).map_err(|e| AssetError::io(path, e))?;
+    ZipArchive::new(file).map_err(|e| AssetError::invalid(&path.display().to_string(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+    use super::*;
+
+    // an empty directory for one test to put packs in
+    fn scratch_directory(test : &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("rustworld-assets-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn write_zip(path : &Path, files : &[(&str, &str)]) {
+        let mut zip = ZipWriter::new(File::create(path).unwrap());
+
+        for (name, contents) in files {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn built_in_assets_are_found_by_name() {
+        let assets = Assets::built_in();
+
+        assert!(assets.read_to_string("data/blocks.json").unwrap().contains("\"blocks\""));
+        assert!(assets.list("textures/blocks").unwrap().contains(&"textures/blocks/stone.png".to_string()));
+        assert!(matches!(assets.read("data/nothing.json"), Err(AssetError::NotFound(_))));
+        assert!(matches!(assets.read("../Cargo.toml"), Err(AssetError::InvalidName(_))));
+    }
+
+    #[test]
+    fn later_packs_override_earlier_ones() {
+        let directory = scratch_directory("override");
+        let folder = directory.join("folder");
+        fs::create_dir_all(folder.join("shaders")).unwrap();
+        fs::write(folder.join("shaders/common.glsl"), "folder").unwrap();
+        fs::write(folder.join("shaders/shader.frag"), "folder").unwrap();
+
+        let zip = directory.join("pack.zip");
+        write_zip(&zip, &[("shaders/shader.frag", "zip"), ("shaders/extra/sky.glsl", "zip")]);
+
+        let assets = Assets::with_packs(&[folder, zip]).unwrap();
+
+        assert_eq!(assets.read_to_string("shaders/shader.frag").unwrap(), "zip");
+        assert_eq!(assets.read_to_string("shaders/common.glsl").unwrap(), "folder");
+        assert!(assets.read_to_string("shaders/shader.vert").unwrap().starts_with("#version"));
+
+        let shaders = assets.list("shaders").unwrap();
+        assert!(shaders.contains(&"shaders/common.glsl".to_string()) && shaders.contains(&"shaders/shader.vert".to_string()));
+        assert!(!shaders.iter().any(|name| name.contains("extra")));
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn watcher_notices_changed_files_once() {
+        let directory = scratch_directory("watch");
+        fs::create_dir_all(directory.join("data")).unwrap();
+        let file = File::create(directory.join("data/blocks.json")).unwrap();
+
+        let assets = Assets::with_packs(std::slice::from_ref(&directory)).unwrap();
+        let mut watcher = AssetWatcher::new(&assets, Duration::ZERO);
+        assert!(!watcher.poll(&assets));
+
+        file.set_modified(SystemTime::now() + Duration::from_secs(60)).unwrap();
+        assert!(watcher.poll(&assets));
+        assert!(!watcher.poll(&assets));
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn watcher_notices_removed_and_restored_files() {
+        let directory = scratch_directory("watch-removed");
+        fs::create_dir_all(directory.join("data")).unwrap();
+        let old = SystemTime::now() - Duration::from_secs(3600);
+        File::create(directory.join("data/blocks.json")).unwrap().set_modified(SystemTime::now()).unwrap();
+        File::create(directory.join("data/models.json")).unwrap().set_modified(old).unwrap();
+
+        let assets = Assets::with_packs(std::slice::from_ref(&directory)).unwrap();
+        let mut watcher = AssetWatcher::new(&assets, Duration::ZERO);
+
+        // neither is the newest file, so only the list of files shows the change
+        fs::remove_file(directory.join("data/models.json")).unwrap();
+        assert!(watcher.poll(&assets));
+        assert!(!watcher.poll(&assets));
+
+        File::create(directory.join("data/models.json")).unwrap().set_modified(old).unwrap();
+        assert!(watcher.poll(&assets));
+        assert!(!watcher.poll(&assets));
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -8,6 +8,7 @@ type Vector3 = cgmath::Vector3<f32>;
 type Matrix4 = cgmath::Matrix4<f32>;
 
 // Defines several possible options for camera movement. Used as abstraction to stay away from window-system specific input methods
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
 #[derive(PartialEq, Clone, Copy)]
 pub enum Camera_Movement {
     FORWARD,
@@ -64,7 +65,7 @@ impl Default for Camera {
 impl Camera {
     /// Returns the view matrix calculated using Euler Angles and the LookAt Matrix
     pub fn get_view_matrix(&self) -> Matrix4 {
-        Matrix4::look_at(self.position, self.position + self.front, self.up)
+        Matrix4::look_at_rh(self.position, self.position + self.front, self.up)
     }
 
     /// Processes input received from any keyboard-like input system. Accepts input parameter in the form of camera defined ENUM (to abstract it from windowing systems)
@@ -100,12 +101,7 @@ impl Camera {
 
         // Make sure that when pitch is out of bounds, screen doesn't get flipped
         if constrain_pitch {
-            if self.pitch > 89.0 {
-                self.pitch = 89.0;
-            }
-            if self.pitch < -89.0 {
-                self.pitch = -89.0;
-            }
+            self.pitch = self.pitch.clamp(-89.0, 89.0);
         }
 
         // Update front, right and up Vectors using the updated Eular angles
@@ -0,0 +1,114 @@
+use std::path::PathBuf;
+use crate::rendering::texture::TextureBackend;
+
+// options given on the command line, e.g. `RustWorld --seed 1234`
+#[derive(Debug, Default)]
+pub struct GameConfig {
+    // start from this world seed instead of a random one
+    pub seed : Option<u32>,
+    // load the world saved in this directory, or create one there, and save it on exit
+    pub world_directory : Option<PathBuf>,
+    // write the packed block textures here, as atlas.png and atlas.json or as layer_N.png and layers.json
+    pub atlas_directory : Option<PathBuf>,
+    // directories or zip files of assets to use over the built-in ones, later ones win
+    pub resource_packs : Vec<PathBuf>,
+    // reload shaders, textures and blocks whenever the asset files change, as well as when R is pressed
+    pub watch_assets : bool,
+    // give block textures to the shader this way instead of game_specs::TEXTURE_BACKEND
+    pub texture_backend : Option<TextureBackend>,
+}
+
+pub const USAGE : &str = "usage: RustWorld [--seed <number>] [--world <directory>] [--dump-atlas <directory>] [--resource-pack <directory or zip>]... [--watch-assets] [--texture-backend <atlas|array>]";
+
+impl GameConfig {
+    // parse the program arguments, not including the program name
+    pub fn from_args<I : IntoIterator<Item = String>>(args : I) -> Result<GameConfig, String> {
+        let mut config = GameConfig::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => {
+                    let value = args.next().ok_or("--seed needs a value")?;
+                    config.seed = Some(parse_seed(&value)?);
+                }
+                "--world" => {
+                    let value = args.next().ok_or("--world needs a directory")?;
+                    config.world_directory = Some(PathBuf::from(value));
+                }
+                "--dump-atlas" => {
+                    let value = args.next().ok_or("--dump-atlas needs a directory")?;
+                    config.atlas_directory = Some(PathBuf::from(value));
+                }
+                "--resource-pack" => {
+                    let value = args.next().ok_or("--resource-pack needs a directory or zip file")?;
+                    config.resource_packs.push(PathBuf::from(value));
+                }
+                "--watch-assets" => config.watch_assets = true,
+                "--texture-backend" => {
+                    let value = args.next().ok_or("--texture-backend needs atlas or array")?;
+                    config.texture_backend = Some(parse_texture_backend(&value)?);
+                }
+                _ => return Err(format!("unknown argument: {}", arg)),
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse_seed(value : &str) -> Result<u32, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid seed '{}', expected a number between 0 and {}", value, u32::MAX))
+}
+
+fn parse_texture_backend(value : &str) -> Result<TextureBackend, String> {
+    match value {
+        "atlas" => Ok(TextureBackend::Atlas),
+        "array" => Ok(TextureBackend::Array),
+        _ => Err(format!("invalid texture backend '{}', expected atlas or array", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args : &[&str]) -> Result<GameConfig, String> {
+        GameConfig::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn seeds_are_numbers() {
+        assert_eq!(parse(&[]).unwrap().seed, None);
+        assert_eq!(parse(&["--seed", "1234"]).unwrap().seed, Some(1234));
+        assert_eq!(parse(&["--seed", "4294967295"]).unwrap().seed, Some(u32::MAX));
+        assert_eq!(parse(&["--world", "saves/one", "--seed", "0"]).unwrap().seed, Some(0));
+    }
+
+    #[test]
+    fn bad_seeds_are_errors() {
+        assert_eq!(parse(&["--seed"]).unwrap_err(), "--seed needs a value");
+
+        for seed in ["hills", "-1", "4294967296", "12.5", ""] {
+            let error = parse(&["--seed", seed]).unwrap_err();
+            assert!(error.starts_with(&format!("invalid seed '{}'", seed)), "{}", error);
+        }
+    }
+
+    #[test]
+    fn unknown_arguments_are_errors() {
+        assert_eq!(parse(&["--sed", "1234"]).unwrap_err(), "unknown argument: --sed");
+        assert_eq!(parse(&["--seed", "1", "1234"]).unwrap_err(), "unknown argument: 1234");
+    }
+
+    #[test]
+    fn texture_backend_can_be_picked() {
+        assert_eq!(parse(&[]).unwrap().texture_backend, None);
+        assert_eq!(parse(&["--texture-backend", "array"]).unwrap().texture_backend, Some(TextureBackend::Array));
+        assert_eq!(parse(&["--texture-backend", "atlas"]).unwrap().texture_backend, Some(TextureBackend::Atlas));
+        assert!(parse(&["--texture-backend", "cubemap"]).is_err());
+        assert!(parse(&["--texture-backend"]).is_err());
+    }
+}
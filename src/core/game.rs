@@ -1,15 +1,28 @@
-use cgmath::{Deg, InnerSpace, Matrix4, perspective, vec3, Vector3};
+use std::io;
+use std::time::Duration;
+use cgmath::{Deg, EuclideanSpace, Matrix4, perspective, Point3};
+use glutin::event::Event;
 use glutin::event_loop::{ControlFlow, EventLoop};
+use crate::core::assets::{AssetWatcher, Assets};
+use crate::core::config::GameConfig;
 use crate::core::game_window::GameWindow;
-use crate::rendering::renderer::Renderer;
+use crate::rendering::gl_object::{context_created, live_counts};
+use crate::rendering::renderer::{pack_block_textures, RenderPath, Renderer};
+use crate::creation::block_registry::{load_block_registry, BlockRegistry, BlockState};
+use crate::creation::chunk_manager::ChunkManager;
+use crate::creation::position::BlockPos;
+use crate::creation::cube::TerrainBlocks;
+use crate::creation::storage::WorldStorage;
 use crate::creation::world::World;
-use crate::game_specs::{CHUNK_SIZE, MAX_CHUNK_HEIGHT};
+use crate::game_specs::{ASSET_POLL_INTERVAL, CHUNK_RADIUS, CHUNK_UNLOAD_HYSTERESIS, CHUNK_WORKER_THREADS, REACH, TEXTURE_BACKEND};
 
-pub struct Game { }
+pub struct Game {
+    config : GameConfig,
+}
 
 impl Game {
-    pub fn new() -> Self {
-        Game { }
+    pub fn new(config : GameConfig) -> Self {
+        Game { config }
     }
 
     pub fn run(&self) {
@@ -20,35 +33,86 @@ impl Game {
 
         // Initialize OpenGL (make opengl functions available within the program)
         gl::load_with(|symbol| window.context.get_proc_address(symbol) as *const _);
+        // GL objects are deleted when they're dropped from here until the window closes
+        context_created();
 
-        let mut world = World::new();
+        let mut assets = Assets::with_packs(&self.config.resource_packs).unwrap_or_else(|error| {
+            eprintln!("Failed to open resource packs: {}", error);
+            std::process::exit(1);
+        });
 
-        let mut cube_positions = Vec::new();
-        let mut cube_types = Vec::new();
+        let blocks = load_block_registry(&assets).unwrap_or_else(|error| {
+            eprintln!("Failed to load blocks: {}", error);
+            std::process::exit(1);
+        });
+        let terrain = TerrainBlocks::from_registry(&blocks).unwrap_or_else(|error| {
+            eprintln!("Failed to load terrain blocks: {}", error);
+            std::process::exit(1);
+        });
 
-        for chunk in &world.chunks {
-            for cube in &chunk.cubes {
-                if !cube.is_blocked(chunk) {
-                    cube_positions.push(cube.position);
-                    cube_types.push(cube._type);
-                }
+        let texture_backend = self.config.texture_backend.unwrap_or(TEXTURE_BACKEND);
+        let textures = pack_block_textures(&assets, texture_backend).unwrap_or_else(|error| {
+            eprintln!("Failed to pack block textures: {}", error);
+            std::process::exit(1);
+        });
+        if let Some(directory) = &self.config.atlas_directory {
+            match textures.write(directory) {
+                Ok(()) => println!("Block atlas written to {}", directory.display()),
+                Err(error) => eprintln!("Failed to write block atlas: {}", error),
             }
         }
 
-        let mut renderer = Renderer::new();
-        renderer.init_renderer(world);
+        let mut world = open_world(&self.config).unwrap_or_else(|error| {
+            eprintln!("Failed to open world: {}", error);
+            std::process::exit(1);
+        });
+        println!("World seed: {}", world.seed());
+        window.camera.position = Point3::from_vec(world.spawn());
+
+        let world_directory = self.config.world_directory.clone();
+        let mut chunk_manager = ChunkManager::new(
+            CHUNK_RADIUS,
+            CHUNK_UNLOAD_HYSTERESIS,
+            CHUNK_WORKER_THREADS,
+            world.seed(),
+            terrain,
+            world.storage()
+        );
+
+        let mut renderer = Renderer::new(&assets, blocks, textures).unwrap_or_else(|error| {
+            eprintln!("Failed to start the renderer: {}", error);
+            std::process::exit(1);
+        });
+        renderer.init_renderer();
+
+        let mut asset_watcher = AssetWatcher::new(&assets, Duration::from_secs_f32(ASSET_POLL_INTERVAL));
+        let watch_assets = self.config.watch_assets;
+        let resource_packs = self.config.resource_packs.clone();
 
         // Initialize variables for tracking time
         let mut last_frame_time = std::time::Instant::now();
-        let mut delta_time = std::time::Duration::from_secs(0);
+        let mut frame_stats = FrameStats::default();
+        let mut breaking = Breaking::default();
 
         // Main event loop runs until application is terminated.
         event_loop.run(move |event, _, control_flow| {
             *control_flow = ControlFlow::Poll;
 
+            // save where we were when the game closes
+            if let Event::LoopDestroyed = event {
+                if let Some(directory) = &world_directory {
+                    world.set_spawn(window.camera.position.to_vec());
+
+                    if let Err(error) = world.save(directory) {
+                        eprintln!("Failed to save world: {}", error);
+                    }
+                }
+                return;
+            }
+
             //calculate time between frames
             let current_frame_time = std::time::Instant::now();
-            delta_time = current_frame_time.duration_since(last_frame_time);
+            let delta_time = current_frame_time.duration_since(last_frame_time);
             last_frame_time = current_frame_time;
 
             // Convert delta_time to seconds as a floating-point number
@@ -67,26 +131,149 @@ impl Game {
 
             let view: Matrix4<f32> = window.camera.get_view_matrix();
 
-            let mut models = (Vec::new(), Vec::new());
-            for i in 0..cube_positions.len() {
-                models.0.push(update_model(&cube_positions[i]));
-                models.1.push(&cube_types[i]);
+            let target = world
+                .target_block(window.camera.position.to_vec(), window.camera.front, REACH)
+                .and_then(|position| Some((position, world.get_block(position)?)));
+
+            // a block breaks once the button has been held on it for its hardness
+            match target {
+                Some((position, block)) if window.breaking => {
+                    if breaking.hold(position, renderer.blocks().hardness(block.id), delta_time) {
+                        world.set_block(position, BlockState::AIR);
+                    }
+                }
+                _ => breaking = Breaking::default(),
+            }
+
+            // load and unload chunks around the camera, and let the renderer know what changed
+            chunk_manager.update(&mut world, window.camera.position.to_vec());
+            for event in chunk_manager.take_events() {
+                renderer.handle_chunk_event(&world, event);
+            }
+
+            if window.mesh_mode != renderer.mesh_mode() {
+                println!("Mesh mode: {:?}", window.mesh_mode);
+                renderer.set_mesh_mode(&world, window.mesh_mode);
+            }
+
+            // open the packs again too, so zip files that have changed are read afresh
+            if std::mem::take(&mut window.reload_requested) || (watch_assets && asset_watcher.poll(&assets)) {
+                match Assets::with_packs(&resource_packs) {
+                    Ok(reopened) => assets = reopened,
+                    Err(error) => eprintln!("Failed to open resource packs: {}", error),
+                }
+
+                match renderer.reload(&assets, &world) {
+                    Ok(()) => println!("Reloaded shaders, textures and blocks"),
+                    Err(error) => eprintln!("Failed to reload assets: {}", error),
+                }
+            }
+
+            if window.render_path != renderer.render_path() {
+                println!("Render path: {:?}", window.render_path);
+                renderer.set_render_path(&world, window.render_path);
+                frame_stats = FrameStats::default();
             }
 
             // render
-            renderer.render(projection, view, models);
+            renderer.render(projection, view);
+
+            if window.show_stats {
+                let looking_at = target.map(|(_, block)| describe_block(renderer.blocks(), block));
+                frame_stats.record(delta_time, renderer.draw_calls(), renderer.render_path(), looking_at);
+            }
 
             window.context.swap_buffers().unwrap();
         });
     }
 }
 
-// TODO this shouldn't be here
-fn update_model(position : &Vector3<f32>) -> Matrix4<f32> {
-    let mut model: Matrix4<f32> = Matrix4::from_translation(*position); //TODO
-    let angle = 0.0;
-    model * Matrix4::from_axis_angle(
-        vec3(1.0, 0.0, 0.0).normalize(),
-        Deg(angle)
-    )
-}
\ No newline at end of file
+// load the world from the configured directory, or start a new one
+fn open_world(config : &GameConfig) -> io::Result<World> {
+    if let Some(directory) = &config.world_directory {
+        if WorldStorage::has_level(directory) {
+            if config.seed.is_some() {
+                println!("Ignoring --seed, {} already contains a world", directory.display());
+            }
+
+            return World::load(directory);
+        }
+    }
+
+    let mut world = match config.seed {
+        Some(seed) => World::with_seed(seed),
+        None => World::new(),
+    };
+
+    // save straight away so edited chunks have somewhere to go when they're unloaded
+    if let Some(directory) = &config.world_directory {
+        world.save(directory)?;
+    }
+
+    Ok(world)
+}
+
+// frame times and draw calls, averaged and printed once a second so render paths can be compared, along with the
+// number of live GL objects so leaks show up as chunks load and unload
+#[derive(Default)]
+struct FrameStats {
+    frames : u32,
+    elapsed : f32,
+    draw_calls : usize,
+}
+
+impl FrameStats {
+    fn record(&mut self, delta_time : f32, draw_calls : usize, render_path : RenderPath, looking_at : Option<String>) {
+        self.frames += 1;
+        self.elapsed += delta_time;
+        self.draw_calls += draw_calls;
+
+        if self.elapsed >= 1.0 {
+            println!("{:?}: {:.2} ms per frame, {} draw calls per frame, live GL objects: {}",
+                     render_path,
+                     self.elapsed * 1000.0 / self.frames as f32,
+                     self.draw_calls / self.frames as usize,
+                     live_counts());
+            if let Some(looking_at) = looking_at {
+                println!("Looking at {}", looking_at);
+            }
+            *self = FrameStats::default();
+        }
+    }
+}
+
+// how long the break button has been held on the block being looked at
+#[derive(Default)]
+struct Breaking {
+    target : Option<BlockPos>,
+    held_for : f32,
+}
+
+impl Breaking {
+    // call every frame the button is held, true once it has been on the same block for the block's hardness
+    fn hold(&mut self, target : BlockPos, hardness : f32, delta_time : f32) -> bool {
+        if self.target != Some(target) {
+            *self = Breaking { target: Some(target), held_for: 0.0 };
+        }
+
+        self.held_for += delta_time;
+        if self.held_for < hardness {
+            return false;
+        }
+
+        *self = Breaking::default();
+        true
+    }
+}
+
+// a block's name and properties, for the frame stats
+fn describe_block(blocks : &BlockRegistry, state : BlockState) -> String {
+    let name = blocks.get(state.id).map_or("an unknown block", |block| block.name.as_str());
+
+    format!("{} (solid: {}, transparent: {}, light level {}, hardness {})",
+            name,
+            blocks.is_solid(state.id),
+            blocks.is_transparent(state.id),
+            blocks.light_level(state.id),
+            blocks.hardness(state.id))
+}
@@ -1,19 +1,30 @@
 use crate::game_specs::*;
 
 use glutin::{ContextBuilder, ContextWrapper, PossiblyCurrent};
-use glutin::dpi::LogicalSize;
-use glutin::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use glutin::event::{ElementState, Event, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent};
 use glutin::event_loop::{ControlFlow, EventLoop};
 use glutin::window::{Window, WindowBuilder};
 use crate::core::lib::polygon_mode;
 use crate::core::lib::PolygonMode::{Fill, Line};
 use crate::core::camera::{Camera, Camera_Movement::*, Point3};
+use crate::rendering::gl_object::context_destroyed;
+use crate::rendering::mesher::MeshMode;
+use crate::rendering::renderer::RenderPath;
 
 pub struct GameWindow {
-    event_loop : EventLoop<()>,
+    _event_loop : EventLoop<()>,
     pub context : ContextWrapper<PossiblyCurrent, Window>,
     pub camera : Camera,
-    last_frame_time: std::time::Instant,
+    // toggled with G, the game rebuilds the chunk meshes when it changes
+    pub mesh_mode : MeshMode,
+    // toggled with I, switches between chunk meshes and instanced cubes
+    pub render_path : RenderPath,
+    // toggled with P, prints frame times and draw calls once a second
+    pub show_stats : bool,
+    // set when R is pressed, the game reloads shaders, textures and blocks and clears it
+    pub reload_requested : bool,
+    // true while the left mouse button is held, the game breaks the block being looked at
+    pub breaking : bool,
     first_mouse : bool,
     last_x : f32,
     last_y : f32,
@@ -36,19 +47,23 @@ impl GameWindow {
         }.unwrap();
 
         let camera = Camera {
-            position: Point3::new(00.0, 10.0, 70.0),
+            position: Point3::from(SPAWN_POSITION),
             ..Camera::default()
         };
 
-        let mut first_mouse = true;
-        let mut last_x: f32 = WINDOW_WIDTH as f32 / 2.0;
-        let mut last_y: f32 = WINDOW_HEIGHT as f32 / 2.0;
+        let first_mouse = true;
+        let last_x: f32 = WINDOW_WIDTH as f32 / 2.0;
+        let last_y: f32 = WINDOW_HEIGHT as f32 / 2.0;
 
         GameWindow {
-            event_loop,
+            _event_loop: event_loop,
             context,
             camera,
-            last_frame_time: std::time::Instant::now(),
+            mesh_mode: MESH_MODE,
+            render_path: RENDER_PATH,
+            show_stats: false,
+            reload_requested: false,
+            breaking: false,
             first_mouse,
             last_x,
             last_y,
@@ -59,8 +74,8 @@ impl GameWindow {
                           event : Event<()>,
                           delta_time : f32,
                           control_flow : &mut ControlFlow) {
-        match event {
-            Event::WindowEvent { event, .. } => match event {
+        if let Event::WindowEvent { event, .. } = event {
+            match event {
                 WindowEvent::CloseRequested => {
                     *control_flow = ControlFlow::Exit;
                 }
@@ -69,6 +84,10 @@ impl GameWindow {
                     self.process_key_input(input, delta_time, control_flow);
                 }
 
+                WindowEvent::MouseInput { state, button: MouseButton::Left, .. } => {
+                    self.breaking = state == ElementState::Pressed;
+                }
+
                 WindowEvent::CursorMoved { position, .. } => {
                     let xpos = position.x as f32;
                     let ypos = position.y as f32;
@@ -90,62 +109,60 @@ impl GameWindow {
 
                 _ => {}
             }
-
-            //This is a catch-all case in the match statement like finally in switch
-            _ => (),
         }
     }
 
     pub fn process_key_input(&mut self, input : KeyboardInput, delta_time : f32, control_flow : &mut ControlFlow) {
         if let Some(key_code) = input.virtual_keycode {
             match key_code {
-                VirtualKeyCode::Escape => {
-                    if input.state == ElementState::Pressed {
-                        *control_flow = ControlFlow::Exit;
-                    }
+                VirtualKeyCode::Escape if input.state == ElementState::Pressed => {
+                    *control_flow = ControlFlow::Exit;
                 }
-                VirtualKeyCode::W => {
-                    if input.state == ElementState::Pressed {
-                        self.camera.process_keyboard(FORWARD, delta_time);
-                    }
+                VirtualKeyCode::W if input.state == ElementState::Pressed => {
+                    self.camera.process_keyboard(FORWARD, delta_time);
                 }
-                VirtualKeyCode::S => {
-                    if input.state == ElementState::Pressed {
-                        self.camera.process_keyboard(BACKWARD, delta_time);
-                    }
+                VirtualKeyCode::S if input.state == ElementState::Pressed => {
+                    self.camera.process_keyboard(BACKWARD, delta_time);
                 }
-                VirtualKeyCode::A => {
-                    if input.state == ElementState::Pressed {
-                        self.camera.process_keyboard(LEFT, delta_time);
-                    }
+                VirtualKeyCode::A if input.state == ElementState::Pressed => {
+                    self.camera.process_keyboard(LEFT, delta_time);
                 }
-                VirtualKeyCode::D => {
-                    if input.state == ElementState::Pressed {
-                        self.camera.process_keyboard(RIGHT, delta_time);
-                    }
+                VirtualKeyCode::D if input.state == ElementState::Pressed => {
+                    self.camera.process_keyboard(RIGHT, delta_time);
                 }
-                VirtualKeyCode::Q => {
-                    if input.state == ElementState::Pressed {
-                        self.camera.process_keyboard(UP, delta_time);
-                    }
+                VirtualKeyCode::Q if input.state == ElementState::Pressed => {
+                    self.camera.process_keyboard(UP, delta_time);
                 }
-                VirtualKeyCode::Z => {
-                    if input.state == ElementState::Pressed {
-                        self.camera.process_keyboard(DOWN, delta_time);
-                    }
+                VirtualKeyCode::Z if input.state == ElementState::Pressed => {
+                    self.camera.process_keyboard(DOWN, delta_time);
                 }
-                VirtualKeyCode::F => {
-                    if input.state == ElementState::Pressed {
-                        polygon_mode(Fill);
-                    }
+                VirtualKeyCode::F if input.state == ElementState::Pressed => {
+                    polygon_mode(Fill);
                 }
-                VirtualKeyCode::L => {
-                    if input.state == ElementState::Pressed {
-                        polygon_mode(Line);
-                    }
+                VirtualKeyCode::L if input.state == ElementState::Pressed => {
+                    polygon_mode(Line);
+                }
+                VirtualKeyCode::G if input.state == ElementState::Pressed => {
+                    self.mesh_mode = self.mesh_mode.toggled();
+                }
+                VirtualKeyCode::I if input.state == ElementState::Pressed => {
+                    self.render_path = self.render_path.toggled();
+                }
+                VirtualKeyCode::P if input.state == ElementState::Pressed => {
+                    self.show_stats = !self.show_stats;
+                }
+                VirtualKeyCode::R if input.state == ElementState::Pressed => {
+                    self.reload_requested = true;
                 }
                 _ => {}
             }
         }
     }
 }
+
+// the context goes with the window, GL objects dropped after it have nothing left to delete
+impl Drop for GameWindow {
+    fn drop(&mut self) {
+        context_destroyed();
+    }
+}
@@ -4,8 +4,6 @@ use gl::types::GLenum;
 /// The polygon display modes you can set.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PolygonMode {
-    /// Just show the points.
-    Point = gl::POINT as isize,
     /// Just show the lines.
     Line = gl::LINE as isize,
     /// Fill in the polygons.
@@ -1,5 +1,13 @@
+pub mod atlas_packer;
 pub mod block_config;
+pub mod block_model;
+pub mod block_registry;
 pub mod chunk;
+pub mod chunk_manager;
+pub mod chunk_workers;
 pub mod cube;
 pub mod world;
-pub mod noise;
\ No newline at end of file
+pub mod migration;
+pub mod noise;
+pub mod position;
+pub mod storage;
\ No newline at end of file
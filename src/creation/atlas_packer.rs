@@ -0,0 +1,396 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs::{self, File};
+use std::path::Path;
+use image::{FilterType, Rgba, RgbaImage};
+use image::imageops::resize;
+use crate::core::assets::{AssetError, Assets};
+use crate::creation::block_config::{AtlasMeta, AtlasSize, BlockConfig, BlockFaceConfig, BlockTypeConfig, MISSING_TEXTURE};
+
+// Builds the block atlas when the game starts from an asset directory of PNGs, either one per block or one per face:
+//   stone.png                    every face of "stone"
+//   grass_top.png, grass_side.png  one face of "grass", used instead of grass.png for that face
+//
+// Every sprite can also be looked up by its own name, e.g. "grass_side", which uses it for every face. That's how
+// blocks.json gives a block another block's textures or a sprite of its own, like the log's "log_top".
+//
+// Sprites are packed into rows, tallest first. Each one is surrounded by `extrusion` copies of its edge pixels so
+// filtering near its edge, including in the smaller mipmaps, only picks up its own colours, and sprites are kept
+// `padding` pixels apart. The rectangles handed to BlockTypeConfig don't include the extruded border.
+//
+// The same sprites can instead be made into the layers of a texture array, one sprite per layer. Layers all have
+// to be the same size, so smaller sprites are scaled up to the size of the largest.
+
+const FACES : [&str; 3] = ["top", "bottom", "side"];
+
+const ATLAS_IMAGE : &str = "atlas.png";
+const ATLAS_JSON : &str = "atlas.json";
+const LAYERS_JSON : &str = "layers.json";
+
+#[derive(Copy, Clone, Debug)]
+pub struct AtlasOptions {
+    // empty pixels between sprites and around the edge of the atlas
+    pub padding : u32,
+    // how far each sprite's edge pixels are repeated outwards
+    pub extrusion : u32,
+}
+
+impl AtlasOptions {
+    // the smallest mipmap that can be sampled without bleeding, where a texel is as wide as the extruded border
+    pub fn max_mipmap_level(&self) -> u32 {
+        self.extrusion.checked_ilog2().unwrap_or(0)
+    }
+}
+
+pub struct PackedAtlas {
+    pub image : RgbaImage,
+    pub config : BlockTypeConfig,
+}
+
+pub struct PackedLayers {
+    // one sprite per layer, all the same size
+    pub layers : Vec<RgbaImage>,
+    pub config : BlockTypeConfig,
+}
+
+// the block textures in whichever form the renderer wants them
+pub enum PackedTextures {
+    Atlas(PackedAtlas),
+    Layers(PackedLayers),
+}
+
+// where a sprite was placed, in pixels
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SpriteRect {
+    pub x : u32,
+    pub y : u32,
+    pub w : u32,
+    pub h : u32,
+    pub layer : u32,
+}
+
+// pack every PNG in the asset directory into one image
+pub fn pack_directory(assets : &Assets, directory : &str, options : AtlasOptions) -> Result<PackedAtlas, AssetError> {
+    let sprites = load_sprites(assets, directory)?;
+    let (image, rects) = pack_sprites(&sprites, options);
+    let config = block_config(&rects, image.width(), image.height());
+
+    Ok(PackedAtlas { image, config })
+}
+
+// put every PNG in the asset directory in its own layer
+pub fn pack_layers(assets : &Assets, directory : &str) -> Result<PackedLayers, AssetError> {
+    let (layers, rects) = stack_sprites(load_sprites(assets, directory)?);
+    let (width, height) = layers[0].dimensions();
+    let config = block_config(&rects, width, height);
+
+    Ok(PackedLayers { layers, config })
+}
+
+// one layer per sprite in name order, scaling any that are smaller than the largest
+fn stack_sprites(sprites : BTreeMap<String, RgbaImage>) -> (Vec<RgbaImage>, HashMap<String, SpriteRect>) {
+    let width = sprites.values().map(RgbaImage::width).max().unwrap_or(1);
+    let height = sprites.values().map(RgbaImage::height).max().unwrap_or(1);
+
+    let mut layers = Vec::new();
+    let mut rects = HashMap::new();
+
+    for (name, image) in sprites {
+        rects.insert(name, SpriteRect { x: 0, y: 0, w: width, h: height, layer: layers.len() as u32 });
+
+        if image.dimensions() == (width, height) {
+            layers.push(image);
+        } else {
+            layers.push(resize(&image, width, height, FilterType::Triangle));
+        }
+    }
+
+    (layers, rects)
+}
+
+// every PNG in the asset directory by name, along with the missing texture if there isn't one
+fn load_sprites(assets : &Assets, directory : &str) -> Result<BTreeMap<String, RgbaImage>, AssetError> {
+    let mut sprites = BTreeMap::new();
+
+    for asset in assets.list(directory)? {
+        let path = Path::new(&asset);
+
+        if path.extension().and_then(|extension| extension.to_str()) != Some("png") {
+            continue;
+        }
+
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .ok_or_else(|| AssetError::InvalidName(asset.clone()))?;
+        let image = image::load_from_memory(&assets.read(&asset)?).map_err(|e| AssetError::invalid(&asset, e))?;
+
+        sprites.insert(name.to_string(), image.to_rgba());
+    }
+
+    sprites.entry(MISSING_TEXTURE.to_string()).or_insert_with(missing_texture);
+
+    Ok(sprites)
+}
+
+impl PackedTextures {
+    // save the textures for inspecting what was packed
+    pub fn write(&self, directory : &Path) -> Result<(), Box<dyn Error>> {
+        match self {
+            PackedTextures::Atlas(atlas) => atlas.write(directory),
+            PackedTextures::Layers(layers) => layers.write(directory),
+        }
+    }
+}
+
+impl PackedAtlas {
+    // save the atlas as atlas.png and atlas.json for inspecting what was packed
+    pub fn write(&self, directory : &Path) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(directory)?;
+
+        self.image.save(directory.join(ATLAS_IMAGE))?;
+        serde_json::to_writer_pretty(File::create(directory.join(ATLAS_JSON))?, &self.config)?;
+
+        Ok(())
+    }
+}
+
+impl PackedLayers {
+    // save each layer as layer_N.png, along with layers.json
+    pub fn write(&self, directory : &Path) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(directory)?;
+
+        for (index, layer) in self.layers.iter().enumerate() {
+            layer.save(directory.join(format!("layer_{}.png", index)))?;
+        }
+        serde_json::to_writer_pretty(File::create(directory.join(LAYERS_JSON))?, &self.config)?;
+
+        Ok(())
+    }
+}
+
+// lay the sprites out in rows and copy them into one image, returning where each one went
+pub fn pack_sprites(sprites : &BTreeMap<String, RgbaImage>, options : AtlasOptions) -> (RgbaImage, HashMap<String, SpriteRect>) {
+    let AtlasOptions { padding, extrusion } = options;
+    let cell = |image : &RgbaImage| (image.width() + 2 * extrusion, image.height() + 2 * extrusion);
+
+    // tallest first so each row wastes as little space as possible, ties stay in name order
+    let mut order : Vec<(&String, &RgbaImage)> = sprites.iter().collect();
+    order.sort_by_key(|(_, image)| Reverse(image.height()));
+
+    // aim for a square atlas, but never narrower than the widest sprite
+    let area : u32 = order.iter().map(|(_, image)| (cell(image).0 + padding) * (cell(image).1 + padding)).sum();
+    let widest = order.iter().map(|(_, image)| cell(image).0 + 2 * padding).max().unwrap_or(1);
+    let width = ((area as f64).sqrt().ceil() as u32).max(widest).next_power_of_two();
+
+    let mut positions = Vec::new();
+    let (mut x, mut y, mut row_height) = (padding, padding, 0);
+
+    for (name, image) in order {
+        let (cell_width, cell_height) = cell(image);
+
+        if x + cell_width + padding > width {
+            x = padding;
+            y += row_height + padding;
+            row_height = 0;
+        }
+
+        positions.push((name, image, x, y));
+        x += cell_width + padding;
+        row_height = row_height.max(cell_height);
+    }
+
+    let mut atlas = RgbaImage::new(width, y + row_height + padding);
+    let mut rects = HashMap::new();
+
+    for (name, image, x, y) in positions {
+        copy_extruded(&mut atlas, image, x, y, extrusion);
+        rects.insert(name.clone(), SpriteRect { x: x + extrusion, y: y + extrusion, w: image.width(), h: image.height(), layer: 0 });
+    }
+
+    (atlas, rects)
+}
+
+// copy a sprite with its top left extruded corner at (x, y), every pixel of the border repeats the nearest edge pixel
+fn copy_extruded(atlas : &mut RgbaImage, image : &RgbaImage, x : u32, y : u32, extrusion : u32) {
+    for dy in 0..image.height() + 2 * extrusion {
+        for dx in 0..image.width() + 2 * extrusion {
+            let source_x = dx.saturating_sub(extrusion).min(image.width() - 1);
+            let source_y = dy.saturating_sub(extrusion).min(image.height() - 1);
+
+            atlas.put_pixel(x + dx, y + dy, *image.get_pixel(source_x, source_y));
+        }
+    }
+}
+
+// the faces of every block with a sprite, a face without its own sprite uses the block's, or the missing texture
+fn block_config(rects : &HashMap<String, SpriteRect>, width : u32, height : u32) -> BlockTypeConfig {
+    let face_config = |rect : SpriteRect| BlockFaceConfig {
+        x: rect.x as i32,
+        y: rect.y as i32,
+        w: rect.w as i32,
+        h: rect.h as i32,
+        layer: rect.layer,
+    };
+
+    let sprite = |block : &str, face : &str| {
+        rects
+            .get(&format!("{}_{}", block, face))
+            .or_else(|| rects.get(block))
+            .or_else(|| rects.get(MISSING_TEXTURE))
+            .copied()
+            .map(face_config)
+            .unwrap()
+    };
+
+    let mut blocks : HashMap<_, _> = rects
+        .keys()
+        .map(|name| block_name(name))
+        .map(|block| (block.to_string(), BlockConfig {
+            top: sprite(block, "top"),
+            bottom: sprite(block, "bottom"),
+            side: sprite(block, "side"),
+        }))
+        .collect();
+
+    // sprites by their own name, unless a block already has it
+    for (name, rect) in rects {
+        blocks.entry(name.clone()).or_insert_with(|| BlockConfig {
+            top: face_config(*rect),
+            bottom: face_config(*rect),
+            side: face_config(*rect),
+        });
+    }
+
+    BlockTypeConfig {
+        blocks,
+        meta: AtlasMeta { size: AtlasSize { w: width as i32, h: height as i32 } },
+    }
+}
+
+// the block a sprite belongs to, "grass_top" and "grass" are both "grass"
+fn block_name(sprite : &str) -> &str {
+    FACES
+        .iter()
+        .find_map(|face| sprite.strip_suffix(face).and_then(|rest| rest.strip_suffix('_')))
+        .unwrap_or(sprite)
+}
+
+// magenta and black squares, obviously wrong wherever it shows up
+fn missing_texture() -> RgbaImage {
+    RgbaImage::from_fn(16, 16, |x, y| {
+        if (x / 8 + y / 8) % 2 == 0 { Rgba([255, 0, 255, 255]) } else { Rgba([0, 0, 0, 255]) }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPTIONS : AtlasOptions = AtlasOptions { padding: 2, extrusion: 4 };
+
+    fn solid(width : u32, height : u32, shade : u8) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, Rgba([shade, shade, shade, 255]))
+    }
+
+    fn sprites() -> BTreeMap<String, RgbaImage> {
+        [("grass_top", solid(16, 16, 10)), ("grass_side", solid(16, 12, 20)), ("dirt", solid(8, 24, 30)), ("stone", solid(32, 16, 40))]
+            .into_iter()
+            .map(|(name, image)| (name.to_string(), image))
+            .collect()
+    }
+
+    #[test]
+    fn sprites_and_their_borders_never_overlap() {
+        let (atlas, rects) = pack_sprites(&sprites(), OPTIONS);
+        let border = OPTIONS.extrusion + OPTIONS.padding;
+
+        // grow each rectangle by its extrusion and half the padding on each side
+        let cells : Vec<_> = rects.values().map(|rect| (rect.x - border, rect.y - border, rect.w + 2 * border, rect.h + 2 * border)).collect();
+
+        for (i, a) in cells.iter().enumerate() {
+            assert!(a.0 + a.2 <= atlas.width() && a.1 + a.3 <= atlas.height());
+
+            for b in &cells[i + 1..] {
+                let apart = a.0 + a.2 - OPTIONS.padding <= b.0 || b.0 + b.2 - OPTIONS.padding <= a.0
+                    || a.1 + a.3 - OPTIONS.padding <= b.1 || b.1 + b.3 - OPTIONS.padding <= a.1;
+                assert!(apart, "{:?} overlaps {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn sprites_are_copied_with_extruded_edges() {
+        let sprites = sprites();
+        let (atlas, rects) = pack_sprites(&sprites, OPTIONS);
+
+        for (name, rect) in &rects {
+            let colour = *sprites[name].get_pixel(0, 0);
+            let extrusion = OPTIONS.extrusion;
+
+            for y in rect.y - extrusion..rect.y + rect.h + extrusion {
+                for x in rect.x - extrusion..rect.x + rect.w + extrusion {
+                    assert_eq!(*atlas.get_pixel(x, y), colour, "{} at ({}, {})", name, x, y);
+                }
+            }
+
+            // the padding around it is left empty
+            assert_eq!(atlas.get_pixel(rect.x - extrusion - 1, rect.y)[3], 0);
+        }
+    }
+
+    #[test]
+    fn faces_fall_back_to_the_block_then_the_missing_texture() {
+        let mut sprites = sprites();
+        sprites.insert(MISSING_TEXTURE.to_string(), missing_texture());
+        let (atlas, rects) = pack_sprites(&sprites, OPTIONS);
+        let config = block_config(&rects, atlas.width(), atlas.height());
+
+        let rect = |name : &str| {
+            let rect = rects[name];
+            (rect.x as f32, rect.y as f32, rect.w as f32, rect.h as f32)
+        };
+
+        assert_eq!(config.get_texture_coordinates("grass", "top"), rect("grass_top"));
+        assert_eq!(config.get_texture_coordinates("grass", "side"), rect("grass_side"));
+        assert_eq!(config.get_texture_coordinates("grass", "bottom"), rect(MISSING_TEXTURE));
+        assert_eq!(config.get_texture_coordinates("dirt", "top"), rect("dirt"));
+        assert_eq!(config.get_texture_coordinates("granite", "side"), rect(MISSING_TEXTURE));
+
+        // a sprite by its own name is used for every face
+        assert_eq!(config.get_texture_coordinates("grass_side", "top"), rect("grass_side"));
+        assert_eq!(config.get_texture_coordinates("grass_top", "bottom"), rect("grass_top"));
+        assert_eq!((config.meta.size.w as u32, config.meta.size.h as u32), atlas.dimensions());
+    }
+
+    #[test]
+    fn layers_hold_one_sprite_each_at_the_largest_size() {
+        let mut sprites = sprites();
+        sprites.insert(MISSING_TEXTURE.to_string(), missing_texture());
+        let (layers, rects) = stack_sprites(sprites.clone());
+        let config = block_config(&rects, 32, 24);
+
+        assert_eq!(layers.len(), 5);
+        assert!(layers.iter().all(|layer| layer.dimensions() == (32, 24)));
+
+        for (name, rect) in rects.iter().filter(|(name, _)| name.as_str() != MISSING_TEXTURE) {
+            let shade = sprites[name].get_pixel(0, 0)[0];
+            assert_eq!(layers[rect.layer as usize].get_pixel(16, 12)[0], shade, "{}", name);
+        }
+
+        assert_eq!(config.get_layer("grass", "top"), rects["grass_top"].layer);
+        assert_eq!(config.get_layer("dirt", "side"), rects["dirt"].layer);
+        assert_eq!(config.get_uv_rect("stone", "top"), (0.0, 0.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn block_texture_directory_packs() {
+        let atlas = pack_directory(&Assets::built_in(), crate::game_specs::BLOCK_TEXTURE_DIRECTORY, OPTIONS).unwrap();
+
+        for block in ["grass", "dirt", "stone", MISSING_TEXTURE] {
+            assert!(atlas.config.blocks.contains_key(block), "no sprites for {}", block);
+        }
+        assert_ne!(atlas.config.get_texture_coordinates("grass", "top"), atlas.config.get_texture_coordinates("grass", "side"));
+    }
+}
@@ -1,51 +1,79 @@
 extern crate serde;
 extern crate serde_json;
 
-use std::error::Error;
-use serde::{Deserialize};
-use std::fs::File;
-use std::io::BufReader;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize)]
+// where each block texture is in the atlas, built by creation::atlas_packer
+
+// the sprite used for faces whose texture isn't in the atlas
+pub const MISSING_TEXTURE : &str = "missing";
+
+#[derive(Debug, Deserialize, Serialize)]
 pub struct BlockFaceConfig {
     pub x: i32,
     pub y: i32,
     pub w: i32,
-    pub h: i32
+    pub h: i32,
+    // which layer the texture is in when block textures are a texture array, always 0 in an atlas
+    #[serde(default)]
+    pub layer: u32,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct BlockConfig {
     pub top: BlockFaceConfig,
     pub bottom: BlockFaceConfig,
     pub side: BlockFaceConfig,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct BlockTypeConfig {
-    pub blocks: std::collections::HashMap<String, BlockConfig>,
+// size of the whole spritesheet in pixels, or of one layer of a texture array
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AtlasSize {
+    pub w: i32,
+    pub h: i32
 }
 
-pub fn load_block_config() -> Result<BlockTypeConfig, Box<dyn Error>> {
-    let file = File::open("resources/data/spritesheet.json")?;
-    let reader = BufReader::new(file);
-    serde_json::from_reader(reader).map_err(|e| Box::new(e) as Box<dyn Error>)
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AtlasMeta {
+    pub size: AtlasSize,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct BlockTypeConfig {
+    pub blocks: std::collections::HashMap<String, BlockConfig>,
+    pub meta: AtlasMeta,
 }
 
 impl BlockTypeConfig {
     pub fn get_texture_coordinates(&self, block_type: &str, face: &str) -> (f32, f32, f32, f32) {
-        if let Some(block_config) = self.blocks.get(block_type) {
-            let face_config = match face {
-                "top" => &block_config.top,
-                "bottom" => &block_config.bottom,
-                "side" => &block_config.side,
-                _ => &block_config.top, // Default to "top" if an invalid face is specified
-            };
+        if let Some(face_config) = self.face_config(block_type, face) {
             (face_config.x as f32, face_config.y as f32, face_config.w as f32, face_config.h as f32)
         } else {
-            (1.0, 1.0, 160.0, 160.0) // Default to a generic texture coordinate // TODO hard-coded width and length
+            // nothing to fall back on, use the whole atlas
+            (0.0, 0.0, self.meta.size.w as f32, self.meta.size.h as f32)
         }
     }
-}
 
+    pub fn get_layer(&self, block_type: &str, face: &str) -> u32 {
+        self.face_config(block_type, face).map_or(0, |face_config| face_config.layer)
+    }
 
+    // the same rectangle as get_texture_coordinates, divided by the size of the atlas so it's in texture coordinates
+    pub fn get_uv_rect(&self, block_type: &str, face: &str) -> (f32, f32, f32, f32) {
+        let (x, y, w, h) = self.get_texture_coordinates(block_type, face);
+        let (atlas_w, atlas_h) = (self.meta.size.w as f32, self.meta.size.h as f32);
+
+        (x / atlas_w, y / atlas_h, w / atlas_w, h / atlas_h)
+    }
+
+    fn face_config(&self, block_type: &str, face: &str) -> Option<&BlockFaceConfig> {
+        let block_config = self.blocks.get(block_type).or_else(|| self.blocks.get(MISSING_TEXTURE))?;
+
+        Some(match face {
+            "top" => &block_config.top,
+            "bottom" => &block_config.bottom,
+            "side" => &block_config.side,
+            _ => &block_config.top, // Default to "top" if an invalid face is specified
+        })
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+use crate::core::assets::{AssetError, Assets};
+
+// Shapes for blocks that aren't a plain cube, defined in resources/data/models.json and picked by a block's "model".
+// A model is a list of elements, each either a box or a cross (two quads crossing diagonally, for plants), with
+// coordinates in sixteenths of a block from 0 to 16:
+//
+//   { "name": "slab", "elements": [ { "from": [0, 0, 0], "to": [16, 8, 16] } ] }
+//
+// A box draws all six faces unless it lists "faces", e.g. "faces": { "top": { "uv": [0, 0, 16, 8], "texture": "side" } }.
+// "uv" is the part of the texture to use as u0, v0, u1, v1 in sixteenths with v going down the texture, it defaults
+// to the part of the texture a full block would show there. "texture" is which of the block's textures the face
+// uses, defaulting to the face's own.
+//
+// The sides of a model that are completely covered are worked out when it's loaded, only those hide the faces of
+// the blocks touching them. "cube" is built in and is meshed like any other full block.
+
+pub const MODELS_FILE : &str = "data/models.json";
+
+// the model blocks use when they don't name one
+pub const CUBE_MODEL : &str = "cube";
+
+// the directions a side of a block can face, see rendering::mesher::Face
+const SIDES : [[i32; 3]; 6] = [[1, 0, 0], [-1, 0, 0], [0, 1, 0], [0, -1, 0], [0, 0, 1], [0, 0, -1]];
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ElementShape {
+    #[default]
+    Box,
+    Cross,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+pub struct ElementFace {
+    #[serde(default)]
+    pub uv : Option<[f32; 4]>,
+    #[serde(default)]
+    pub texture : Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct ModelElement {
+    pub from : [f32; 3],
+    pub to : [f32; 3],
+    #[serde(default)]
+    pub shape : ElementShape,
+    // keyed by "top", "bottom", "north", "south", "east" or "west", every face is drawn if there are none
+    #[serde(default)]
+    pub faces : HashMap<String, ElementFace>,
+}
+
+impl ModelElement {
+    // the face drawn on one side of a box, None if the box leaves that side out
+    pub fn face(&self, side : &str) -> Option<ElementFace> {
+        if self.faces.is_empty() {
+            return Some(ElementFace::default());
+        }
+
+        self.faces.get(side).cloned()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelDefinition {
+    name : String,
+    elements : Vec<ModelElement>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModelDefinitions {
+    models : Vec<ModelDefinition>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockModel {
+    pub elements : Vec<ModelElement>,
+    // sides of the block that are completely covered by a face, in SIDES order
+    full_sides : [bool; 6],
+    cube : bool,
+}
+
+pub fn load_block_models(assets : &Assets) -> Result<HashMap<String, BlockModel>, AssetError> {
+    let definitions : ModelDefinitions = serde_json::from_slice(&assets.read(MODELS_FILE)?)
+        .map_err(|e| AssetError::invalid(MODELS_FILE, e))?;
+
+    let mut models = HashMap::new();
+
+    for definition in definitions.models {
+        let model = BlockModel::new(&definition.name, definition.elements)?;
+
+        if models.insert(definition.name.clone(), model).is_some() {
+            return Err(AssetError::invalid(MODELS_FILE, format!("more than one model is called '{}'", definition.name)));
+        }
+    }
+
+    Ok(models)
+}
+
+impl BlockModel {
+    // name is only used in errors
+    pub fn new(name : &str, elements : Vec<ModelElement>) -> Result<BlockModel, AssetError> {
+        for element in &elements {
+            let in_block = (0..3).all(|i| 0.0 <= element.from[i] && element.from[i] <= element.to[i] && element.to[i] <= 16.0);
+
+            if !in_block {
+                return Err(AssetError::invalid(MODELS_FILE, format!("an element of model '{}' from {:?} to {:?} isn't inside the block",
+                                                                    name, element.from, element.to)));
+            }
+        }
+
+        let full_sides = SIDES.map(|side| covers_side(&elements, side));
+        let cube = match elements.as_slice() {
+            [element] => element.shape == ElementShape::Box && element.faces.is_empty() && full_sides.iter().all(|full| *full),
+            _ => false,
+        };
+
+        Ok(BlockModel { elements, full_sides, cube })
+    }
+
+    // a single box filling the whole block
+    pub fn cube() -> BlockModel {
+        BlockModel::new(CUBE_MODEL, vec![ModelElement {
+            from: [0.0; 3],
+            to: [16.0; 3],
+            shape: ElementShape::Box,
+            faces: HashMap::new(),
+        }]).unwrap()
+    }
+
+    // true if the model is the same as cube(), these blocks can be meshed without looking at their elements
+    pub fn is_cube(&self) -> bool {
+        self.cube
+    }
+
+    // true if the side facing in a direction is completely covered
+    pub fn is_full(&self, side : [i32; 3]) -> bool {
+        SIDES.iter().position(|candidate| *candidate == side).is_some_and(|index| self.full_sides[index])
+    }
+}
+
+// true if the box faces lying on one side of the block cover every sixteenth of it
+fn covers_side(elements : &[ModelElement], side : [i32; 3]) -> bool {
+    let axis = side.iter().position(|component| *component != 0).unwrap();
+    let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+    let mut covered = [[false; 16]; 16];
+
+    for element in elements.iter().filter(|element| element.shape == ElementShape::Box) {
+        let on_side = if side[axis] > 0 { element.to[axis] == 16.0 } else { element.from[axis] == 0.0 };
+
+        if !on_side || element.face(side_name(side)).is_none() {
+            continue;
+        }
+
+        for (i, row) in covered.iter_mut().enumerate() {
+            for (j, cell) in row.iter_mut().enumerate() {
+                let (i, j) = (i as f32, j as f32);
+                let inside = element.from[a] <= i && i + 1.0 <= element.to[a] && element.from[b] <= j && j + 1.0 <= element.to[b];
+                *cell |= inside;
+            }
+        }
+    }
+
+    covered.iter().all(|row| row.iter().all(|cell| *cell))
+}
+
+// what a side of a block is called in blocks.json and models.json, north is towards -z and east towards +x
+pub fn side_name(side : [i32; 3]) -> &'static str {
+    match side {
+        [1, 0, 0] => "east",
+        [-1, 0, 0] => "west",
+        [0, 1, 0] => "top",
+        [0, -1, 0] => "bottom",
+        [0, 0, 1] => "south",
+        _ => "north",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model(json : &str) -> BlockModel {
+        BlockModel::new("test", serde_json::from_str(json).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn models_file_loads() {
+        let models = load_block_models(&Assets::built_in()).unwrap();
+
+        for name in ["slab", "stairs", "cross"] {
+            assert!(!models[name].is_cube(), "{} shouldn't be meshed as a cube", name);
+        }
+    }
+
+    #[test]
+    fn only_covered_sides_are_full() {
+        let cube = BlockModel::cube();
+        assert!(cube.is_cube() && SIDES.iter().all(|side| cube.is_full(*side)));
+
+        let slab = model(r#"[ { "from": [0, 0, 0], "to": [16, 8, 16] } ]"#);
+        assert!(slab.is_full([0, -1, 0]));
+        assert!(!slab.is_full([0, 1, 0]) && !slab.is_full([1, 0, 0]));
+
+        let stairs = model(r#"[ { "from": [0, 0, 0], "to": [16, 8, 16] }, { "from": [0, 8, 0], "to": [16, 16, 8] } ]"#);
+        assert!(stairs.is_full([0, -1, 0]) && stairs.is_full([0, 0, -1]));
+        assert!(!stairs.is_full([0, 1, 0]) && !stairs.is_full([0, 0, 1]) && !stairs.is_full([-1, 0, 0]));
+
+        // a box that leaves out its bottom face doesn't cover the bottom
+        let open = model(r#"[ { "from": [0, 0, 0], "to": [16, 16, 16], "faces": { "top": {} } } ]"#);
+        assert!(!open.is_cube() && open.is_full([0, 1, 0]) && !open.is_full([0, -1, 0]));
+
+        let plant = model(r#"[ { "from": [0, 0, 0], "to": [16, 16, 16], "shape": "cross" } ]"#);
+        assert!(SIDES.iter().all(|side| !plant.is_full(*side)));
+    }
+
+    #[test]
+    fn elements_outside_the_block_are_rejected() {
+        let elements = serde_json::from_str(r#"[ { "from": [0, 0, 0], "to": [16, 20, 16] } ]"#).unwrap();
+        assert!(matches!(BlockModel::new("tall", elements), Err(AssetError::Invalid { .. })));
+    }
+}
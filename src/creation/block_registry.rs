@@ -0,0 +1,575 @@
+use std::collections::HashMap;
+use serde::Deserialize;
+use crate::core::assets::{AssetError, Assets};
+use crate::creation::block_model::{load_block_models, BlockModel, CUBE_MODEL};
+
+// Every kind of block is defined in resources/data/blocks.json, so adding a block only needs a new entry there
+// (and its textures in resources/textures/blocks). Chunks store block ids, which are also what gets saved, so a block's id
+// must never change once worlds have been saved with it.
+//
+// A block can also have properties, e.g. the axis a log lies along, each with a list of values (the first is the
+// default). Variants change how the block looks when its properties have certain values:
+//
+//   "properties": [ { "name": "axis", "values": ["y", "x", "z"] } ],
+//   "variants": [
+//     { "when": { "axis": "x" }, "rotation": { "x": 90, "y": 90 } },
+//     { "when": { "axis": "z" }, "rotation": { "x": 90 } }
+//   ]
+//
+// Every matching variant applies, later ones override the textures and rotation of earlier ones. Textures can be
+// given for "top", "bottom", "side" or a single side ("north", "south", "east" or "west"). Rotations are quarter
+// turns around the x axis and then the y axis, which is enough to point any face of a block in any direction.
+//
+// Blocks are cubes unless they name one of the shapes in models.json as their "model" (see creation::block_model).
+
+// the asset the blocks are loaded from, see core::assets
+pub const BLOCKS_FILE : &str = "data/blocks.json";
+
+// the number a block is stored as in chunks and save files
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u8);
+
+impl BlockId {
+    // air is always id 0, empty chunks are filled with it
+    pub const AIR : BlockId = BlockId(0);
+}
+
+// a block as it's stored in a chunk: its id and the values of its properties, packed into one byte
+// (see BlockDefinition::property)
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BlockState {
+    pub id : BlockId,
+    pub properties : u8,
+}
+
+impl BlockState {
+    pub const AIR : BlockState = BlockState { id: BlockId::AIR, properties: 0 };
+
+    // a block with every property set to its first value
+    pub fn new(id : BlockId) -> BlockState {
+        BlockState { id, properties: 0 }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BlockProperty {
+    pub name : String,
+    pub values : Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BlockVariant {
+    // property values the block needs for the variant to apply
+    #[serde(default)]
+    pub when : HashMap<String, String>,
+    #[serde(default)]
+    pub textures : HashMap<String, String>,
+    #[serde(default)]
+    pub rotation : Option<Rotation>,
+}
+
+// quarter turns in degrees, around the x axis first and then the y axis
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+pub struct Rotation {
+    #[serde(default)]
+    pub x : i32,
+    #[serde(default)]
+    pub y : i32,
+}
+
+impl Rotation {
+    // turn a direction of the unrotated block to where it points once rotated
+    pub fn apply(self, direction : [i32; 3]) -> [i32; 3] {
+        rotate_y(rotate_x(direction, self.x), self.y)
+    }
+
+    // the direction of the unrotated block that ends up pointing in the given direction
+    pub fn undo(self, direction : [i32; 3]) -> [i32; 3] {
+        rotate_x(rotate_y(direction, -self.y), -self.x)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BlockDefinition {
+    pub id : u8,
+    pub name : String,
+    // sprites in the block atlas to use for each face, defaulting to the block's name
+    #[serde(default)]
+    pub textures : HashMap<String, String>,
+    #[serde(default = "default_model")]
+    pub model : String,
+    #[serde(default)]
+    pub properties : Vec<BlockProperty>,
+    #[serde(default)]
+    pub variants : Vec<BlockVariant>,
+    // blocks the player can't move through
+    #[serde(default = "default_true")]
+    pub solid : bool,
+    // blocks that completely hide the faces of blocks behind them
+    #[serde(default = "default_true")]
+    pub opaque : bool,
+    // blocks whose textures can be seen through
+    #[serde(default)]
+    pub transparent : bool,
+    // light given off by the block, from 0 to 15
+    #[serde(default)]
+    pub light_level : u8,
+    // how many seconds the block takes to break
+    #[serde(default = "default_hardness")]
+    pub hardness : f32,
+}
+
+impl BlockDefinition {
+    // the texture a face of the unrotated block uses, "side" covers the four sides. Either a block whose sprite for
+    // that face is used, or the name of a single sprite (see creation::atlas_packer)
+    pub fn texture(&self, properties : u8, face : &str) -> &str {
+        let variants = self.matching_variants(properties).rev().map(|variant| &variant.textures);
+
+        for textures in variants.chain([&self.textures]) {
+            let texture = textures.get(face).or_else(|| match face {
+                "top" | "bottom" => None,
+                _ => textures.get("side"),
+            });
+
+            if let Some(texture) = texture {
+                return texture;
+            }
+        }
+
+        &self.name
+    }
+
+    pub fn rotation(&self, properties : u8) -> Rotation {
+        self.matching_variants(properties)
+            .rev()
+            .find_map(|variant| variant.rotation)
+            .unwrap_or_default()
+    }
+
+    // number of different combinations of property values the block can have
+    pub fn state_count(&self) -> usize {
+        self.properties.iter().map(|property| property.values.len()).product()
+    }
+
+    // The properties byte counts through every combination of values, with the first property changing fastest.
+    // With two values for "snowy" and three for "axis", 0 is (false, y), 1 is (true, y), 2 is (false, x) and so on.
+    pub fn property(&self, properties : u8, name : &str) -> Option<&str> {
+        let mut remaining = properties as usize;
+
+        for property in &self.properties {
+            let count = property.values.len();
+
+            if property.name == name {
+                return property.values.get(remaining % count).map(String::as_str);
+            }
+
+            remaining /= count;
+        }
+
+        None
+    }
+
+    // the properties byte with one property changed, None if the block has no such property or value. Only tests
+    // set properties until blocks can be placed
+    #[cfg(test)]
+    pub fn with_property(&self, properties : u8, name : &str, value : &str) -> Option<u8> {
+        let mut place = 1;
+
+        for property in &self.properties {
+            let count = property.values.len();
+
+            if property.name == name {
+                let current = (properties as usize / place) % count;
+                let new = property.values.iter().position(|candidate| candidate == value)?;
+                return Some((properties as usize - current * place + new * place) as u8);
+            }
+
+            place *= count;
+        }
+
+        None
+    }
+
+    fn matching_variants(&self, properties : u8) -> impl DoubleEndedIterator<Item = &BlockVariant> {
+        self.variants.iter().filter(move |variant| {
+            variant.when.iter().all(|(name, value)| self.property(properties, name) == Some(value.as_str()))
+        })
+    }
+
+    // check the properties fit in a byte and the variants only use properties the block has
+    fn validate(&self) -> Result<(), AssetError> {
+        if self.properties.iter().any(|property| property.values.is_empty()) {
+            return Err(invalid_blocks(format!("block '{}' has a property with no values", self.name)));
+        }
+
+        if self.state_count() > 256 {
+            return Err(invalid_blocks(format!("block '{}' has {} combinations of properties, at most 256 are allowed",
+                                              self.name, self.state_count())));
+        }
+
+        for variant in &self.variants {
+            for (name, value) in &variant.when {
+                let known = self.properties.iter().any(|property| &property.name == name && property.values.contains(value));
+
+                if !known {
+                    return Err(invalid_blocks(format!("a variant of block '{}' needs {} = {}, which the block can't have",
+                                                      self.name, name, value)));
+                }
+            }
+
+            if let Some(rotation) = variant.rotation {
+                if rotation.x % 90 != 0 || rotation.y % 90 != 0 {
+                    return Err(invalid_blocks(format!("block '{}' can only be rotated in quarter turns", self.name)));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct BlockDefinitions {
+    blocks : Vec<BlockDefinition>,
+}
+
+#[derive(Debug)]
+pub struct BlockRegistry {
+    // indexed by id
+    blocks : Vec<Option<BlockDefinition>>,
+    ids : HashMap<String, BlockId>,
+    // the model of each block, indexed by id, blocks that aren't defined are cubes
+    models : Vec<BlockModel>,
+}
+
+pub fn load_block_registry(assets : &Assets) -> Result<BlockRegistry, AssetError> {
+    let definitions : BlockDefinitions = serde_json::from_slice(&assets.read(BLOCKS_FILE)?)
+        .map_err(|e| AssetError::invalid(BLOCKS_FILE, e))?;
+
+    BlockRegistry::new(definitions.blocks, load_block_models(assets)?)
+}
+
+// blocks.json can be read but doesn't make sense
+fn invalid_blocks(message : String) -> AssetError {
+    AssetError::invalid(BLOCKS_FILE, message)
+}
+
+impl BlockRegistry {
+    // check the definitions fit together: ids and names are unique, id 0 is air and every model exists
+    pub fn new(definitions : Vec<BlockDefinition>,
+               mut models : HashMap<String, BlockModel>) -> Result<BlockRegistry, AssetError> {
+        models.entry(CUBE_MODEL.to_string()).or_insert_with(BlockModel::cube);
+
+        let mut registry = BlockRegistry {
+            blocks: Vec::new(),
+            ids: HashMap::new(),
+            models: Vec::new(),
+        };
+
+        for definition in definitions {
+            let id = definition.id as usize;
+
+            if registry.blocks.len() <= id {
+                registry.blocks.resize_with(id + 1, || None);
+                registry.models.resize_with(id + 1, BlockModel::cube);
+            }
+
+            if let Some(existing) = &registry.blocks[id] {
+                return Err(invalid_blocks(format!("blocks '{}' and '{}' both have id {}", existing.name, definition.name, id)));
+            }
+
+            definition.validate()?;
+
+            let model = models
+                .get(&definition.model)
+                .ok_or_else(|| invalid_blocks(format!("block '{}' uses model '{}', which isn't defined", definition.name, definition.model)))?;
+
+            if registry.ids.insert(definition.name.clone(), BlockId(definition.id)).is_some() {
+                return Err(invalid_blocks(format!("more than one block is called '{}'", definition.name)));
+            }
+
+            registry.models[id] = model.clone();
+            registry.blocks[id] = Some(definition);
+        }
+
+        match registry.get(BlockId::AIR) {
+            Some(air) if air.name == "air" && !air.opaque => Ok(registry),
+            _ => Err(invalid_blocks("block 0 must be a non-opaque block called 'air'".to_string())),
+        }
+    }
+
+    // None if no block has the id, e.g. a block that was removed after a world was saved
+    pub fn get(&self, id : BlockId) -> Option<&BlockDefinition> {
+        self.blocks.get(id.0 as usize).and_then(Option::as_ref)
+    }
+
+    pub fn id(&self, name : &str) -> Option<BlockId> {
+        self.ids.get(name).copied()
+    }
+
+    // like id, but an error naming the block if it isn't defined
+    pub fn require(&self, name : &str) -> Result<BlockId, AssetError> {
+        self.id(name).ok_or_else(|| invalid_blocks(format!("there's no block called '{}'", name)))
+    }
+
+    // true if the block hides the faces of the blocks touching it, unknown blocks are treated like air
+    pub fn is_opaque(&self, id : BlockId) -> bool {
+        self.get(id).is_some_and(|block| block.opaque)
+    }
+
+    // the rest of the block's properties, unknown blocks are treated like air
+    pub fn is_solid(&self, id : BlockId) -> bool {
+        self.get(id).is_some_and(|block| block.solid)
+    }
+
+    pub fn is_transparent(&self, id : BlockId) -> bool {
+        self.get(id).is_some_and(|block| block.transparent)
+    }
+
+    pub fn light_level(&self, id : BlockId) -> u8 {
+        self.get(id).map_or(0, |block| block.light_level)
+    }
+
+    pub fn hardness(&self, id : BlockId) -> f32 {
+        self.get(id).map_or(0.0, |block| block.hardness)
+    }
+
+    pub fn model(&self, id : BlockId) -> &BlockModel {
+        self.models.get(id.0 as usize).unwrap_or(&self.models[0])
+    }
+
+    // true if the block is drawn, unknown blocks are treated like air
+    pub fn is_visible(&self, id : BlockId) -> bool {
+        id != BlockId::AIR && self.get(id).is_some()
+    }
+}
+
+// quarter turns around the x axis, taking y towards z
+fn rotate_x([x, y, z] : [i32; 3], degrees : i32) -> [i32; 3] {
+    match degrees.rem_euclid(360) {
+        90 => [x, -z, y],
+        180 => [x, -y, -z],
+        270 => [x, z, -y],
+        _ => [x, y, z],
+    }
+}
+
+// quarter turns around the y axis, taking z towards x
+fn rotate_y([x, y, z] : [i32; 3], degrees : i32) -> [i32; 3] {
+    match degrees.rem_euclid(360) {
+        90 => [z, y, -x],
+        180 => [-x, y, -z],
+        270 => [-z, y, x],
+        _ => [x, y, z],
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_model() -> String {
+    CUBE_MODEL.to_string()
+}
+
+fn default_hardness() -> f32 {
+    1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::creation::atlas_packer::pack_directory;
+    use crate::creation::block_config::MISSING_TEXTURE;
+    use crate::game_specs::{ATLAS_OPTIONS, BLOCK_TEXTURE_DIRECTORY};
+    use crate::rendering::mesher::{face_texture, Face};
+    use super::*;
+
+    fn definitions(json : &str) -> Vec<BlockDefinition> {
+        serde_json::from_str::<BlockDefinitions>(json).unwrap().blocks
+    }
+
+    #[test]
+    fn blocks_file_loads() {
+        let registry = load_block_registry(&Assets::built_in()).unwrap();
+
+        assert_eq!(registry.id("air"), Some(BlockId::AIR));
+        for name in ["grass", "dirt", "stone"] {
+            let id = registry.require(name).unwrap();
+            assert!(registry.is_opaque(id) && registry.is_visible(id));
+        }
+        assert!(!registry.is_visible(BlockId::AIR));
+    }
+
+    #[test]
+    fn shipped_blocks_have_their_properties() {
+        let registry = load_block_registry(&Assets::built_in()).unwrap();
+        let (stone, tall_grass) = (registry.require("stone").unwrap(), registry.require("tall_grass").unwrap());
+
+        assert!(registry.is_solid(stone) && !registry.is_transparent(stone));
+        assert_eq!((registry.light_level(stone), registry.hardness(stone)), (0, 1.5));
+
+        // plants can be walked through, seen through and broken straight away
+        assert!(!registry.is_solid(tall_grass) && registry.is_transparent(tall_grass));
+        assert_eq!(registry.hardness(tall_grass), 0.0);
+
+        // like air
+        assert!(!registry.is_solid(BlockId::AIR) && !registry.is_solid(BlockId(200)));
+        assert_eq!((registry.light_level(BlockId(200)), registry.hardness(BlockId(200))), (0, 0.0));
+    }
+
+    #[test]
+    fn shipped_log_lies_along_its_axis() {
+        let registry = load_block_registry(&Assets::built_in()).unwrap();
+        let log = registry.get(registry.require("log").unwrap()).unwrap();
+
+        assert_eq!(log.state_count(), 3);
+        assert_eq!(log.texture(0, "top"), "log_top");
+        assert_eq!(log.texture(0, "east"), "log_side");
+
+        // the end of the log points along its axis
+        for (axis, end) in [("y", [0, 1, 0]), ("x", [1, 0, 0]), ("z", [0, 0, 1])] {
+            let properties = log.with_property(0, "axis", axis).unwrap();
+            assert_eq!(log.property(properties, "axis"), Some(axis));
+            assert_eq!(log.rotation(properties).apply([0, 1, 0]).map(i32::abs), end);
+        }
+
+        // every face is meshed with one of the log's own textures, the ends with log_top and the rest with bark
+        let textures = pack_directory(&Assets::built_in(), BLOCK_TEXTURE_DIRECTORY, ATLAS_OPTIONS).unwrap().config;
+        let rect = |sprite| textures.get_uv_rect(sprite, "side");
+        assert_ne!(rect("log_top"), rect(MISSING_TEXTURE));
+        assert_ne!(rect("log_side"), rect(MISSING_TEXTURE));
+
+        let properties = log.with_property(0, "axis", "x").unwrap();
+        for face in Face::ALL {
+            let tile = face_texture(&textures, log, properties, face).tile.rect;
+            let expected = if face.normal()[0] != 0 { rect("log_top") } else { rect("log_side") };
+            assert_eq!((tile.x, tile.y, tile.z, tile.w), expected, "{:?} face of the log", face.name());
+        }
+    }
+
+    #[test]
+    fn blocks_without_a_model_are_cubes() {
+        let registry = load_block_registry(&Assets::built_in()).unwrap();
+
+        assert!(registry.model(registry.require("stone").unwrap()).is_cube());
+        assert!(!registry.model(registry.require("stone_slab").unwrap()).is_cube());
+        assert!(registry.model(BlockId(200)).is_cube());
+
+        let missing_model = r#"{ "blocks": [
+            { "id": 0, "name": "air", "opaque": false }, { "id": 1, "name": "a", "model": "teapot" }
+        ] }"#;
+        assert!(matches!(BlockRegistry::new(definitions(missing_model), HashMap::new()), Err(AssetError::Invalid { .. })));
+    }
+
+    #[test]
+    fn textures_default_to_the_block_name() {
+        let registry = BlockRegistry::new(definitions(r#"{ "blocks": [
+            { "id": 0, "name": "air", "opaque": false },
+            { "id": 7, "name": "log", "textures": { "top": "log_end", "bottom": "log_end" } }
+        ] }"#), HashMap::new()).unwrap();
+
+        let log = registry.get(registry.require("log").unwrap()).unwrap();
+        assert_eq!(log.texture(0, "top"), "log_end");
+        assert_eq!(log.texture(0, "north"), "log");
+        assert!(log.solid && log.opaque && !log.transparent);
+        assert!(registry.get(BlockId(3)).is_none());
+    }
+
+    #[test]
+    fn duplicate_or_missing_blocks_are_rejected() {
+        let duplicate_id = r#"{ "blocks": [
+            { "id": 0, "name": "air", "opaque": false }, { "id": 1, "name": "a" }, { "id": 1, "name": "b" }
+        ] }"#;
+        let duplicate_name = r#"{ "blocks": [
+            { "id": 0, "name": "air", "opaque": false }, { "id": 1, "name": "a" }, { "id": 2, "name": "a" }
+        ] }"#;
+        let no_air = r#"{ "blocks": [ { "id": 1, "name": "a" } ] }"#;
+        let unknown_property = r#"{ "blocks": [
+            { "id": 0, "name": "air", "opaque": false },
+            { "id": 1, "name": "a", "variants": [ { "when": { "axis": "x" } } ] }
+        ] }"#;
+        let half_turn = r#"{ "blocks": [
+            { "id": 0, "name": "air", "opaque": false },
+            { "id": 1, "name": "a", "properties": [ { "name": "axis", "values": ["y", "x"] } ],
+              "variants": [ { "when": { "axis": "x" }, "rotation": { "x": 45 } } ] }
+        ] }"#;
+
+        for json in [duplicate_id, duplicate_name, no_air, unknown_property, half_turn] {
+            assert!(matches!(BlockRegistry::new(definitions(json), HashMap::new()), Err(AssetError::Invalid { .. })));
+        }
+
+        let registry = BlockRegistry::new(definitions(r#"{ "blocks": [
+            { "id": 0, "name": "air", "opaque": false }, { "id": 1, "name": "a" }
+        ] }"#), HashMap::new()).unwrap();
+        assert!(matches!(registry.require("b"), Err(AssetError::Invalid { ref name, .. }) if name == BLOCKS_FILE));
+    }
+
+    #[test]
+    fn properties_pack_into_one_byte() {
+        let registry = BlockRegistry::new(definitions(r#"{ "blocks": [
+            { "id": 0, "name": "air", "opaque": false },
+            { "id": 1, "name": "log", "properties": [
+                { "name": "snowy", "values": ["false", "true"] },
+                { "name": "axis", "values": ["y", "x", "z"] }
+            ] }
+        ] }"#), HashMap::new()).unwrap();
+        let log = registry.get(BlockId(1)).unwrap();
+
+        assert_eq!(log.state_count(), 6);
+        assert_eq!((log.property(0, "snowy"), log.property(0, "axis")), (Some("false"), Some("y")));
+        assert_eq!((log.property(5, "snowy"), log.property(5, "axis")), (Some("true"), Some("z")));
+
+        let sideways = log.with_property(1, "axis", "x").unwrap();
+        assert_eq!(sideways, 3);
+        assert_eq!(log.with_property(sideways, "snowy", "false"), Some(2));
+        assert_eq!(log.with_property(0, "axis", "w"), None);
+        assert_eq!(log.property(0, "facing"), None);
+    }
+
+    #[test]
+    fn matching_variants_override_textures_and_rotation() {
+        let registry = BlockRegistry::new(definitions(r#"{ "blocks": [
+            { "id": 0, "name": "air", "opaque": false },
+            { "id": 1, "name": "furnace", "textures": { "north": "furnace_front", "side": "furnace_side" },
+              "properties": [
+                { "name": "facing", "values": ["north", "east"] },
+                { "name": "lit", "values": ["false", "true"] }
+              ],
+              "variants": [
+                { "when": { "facing": "east" }, "rotation": { "y": 270 } },
+                { "when": { "lit": "true" }, "textures": { "north": "furnace_lit" } }
+              ] }
+        ] }"#), HashMap::new()).unwrap();
+        let furnace = registry.get(BlockId(1)).unwrap();
+        let east_lit = furnace.with_property(furnace.with_property(0, "facing", "east").unwrap(), "lit", "true").unwrap();
+
+        assert_eq!(furnace.texture(0, "north"), "furnace_front");
+        assert_eq!(furnace.texture(0, "west"), "furnace_side");
+        assert_eq!(furnace.texture(0, "top"), "furnace");
+        assert_eq!(furnace.texture(east_lit, "north"), "furnace_lit");
+        assert_eq!(furnace.rotation(0), Rotation::default());
+
+        // the front of the furnace is turned to face east
+        let rotation = furnace.rotation(east_lit);
+        assert_eq!(rotation.apply([0, 0, -1]), [1, 0, 0]);
+        assert_eq!(rotation.undo([1, 0, 0]), [0, 0, -1]);
+    }
+
+    #[test]
+    fn rotations_can_be_undone() {
+        let directions = [[1, 0, 0], [-1, 0, 0], [0, 1, 0], [0, -1, 0], [0, 0, 1], [0, 0, -1]];
+
+        for x in [0, 90, 180, 270] {
+            for y in [0, 90, 180, 270] {
+                let rotation = Rotation { x, y };
+
+                for direction in directions {
+                    assert_eq!(rotation.undo(rotation.apply(direction)), direction);
+                }
+            }
+        }
+
+        // a log lying along x has its ends pointing east and west
+        assert_eq!(Rotation { x: 90, y: 90 }.apply([0, 1, 0]), [1, 0, 0]);
+    }
+}
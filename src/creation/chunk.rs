@@ -1,88 +1,231 @@
-use cgmath::{Vector3};
-use noise::{NoiseFn, OpenSimplex};
-use crate::creation::cube::{Cube, CubeType, determine_cube_type};
+use crate::creation::block_registry::{BlockId, BlockState};
+use crate::creation::cube::{determine_block, TerrainBlocks};
 use crate::creation::noise::{get_layered_noise, Noise};
-use crate::game_specs::{CHUNK_SIZE, MAX_CHUNK_HEIGHT, MIN_CHUNK_HEIGHT};
+use crate::creation::position::{BlockPos, ChunkPos, LocalPos};
+use crate::game_specs::{CHUNK_SIZE, MAX_CHUNK_HEIGHT};
+
+// number of blocks stored in a single chunk
+pub const CHUNK_VOLUME : usize = CHUNK_SIZE * MAX_CHUNK_HEIGHT * CHUNK_SIZE;
 
 #[derive(Clone)]
 pub struct Chunk {
-    pub position : Vector3<f32>,
-    pub cubes : Vec<Cube>,
+    pub position : ChunkPos,
+    blocks : Vec<BlockState>,
+    // set when the chunk's blocks change and it needs to be rebuilt for rendering
+    dirty : bool,
+    // set when the chunk has been edited since it was generated or last saved
+    edited : bool,
 }
 
 impl Chunk {
-    pub fn new(position : Vector3<f32>, cubes : Vec<Cube>) -> Self {
+    // create an empty chunk filled with air
+    pub fn new(position : ChunkPos) -> Self {
         Chunk {
             position,
-            cubes
+            blocks: vec![BlockState::AIR; CHUNK_VOLUME],
+            dirty: true,
+            edited: false,
         }
     }
 
-    pub fn generate(position: Vector3<f32>, neighbor_chunk: Option<&Chunk>, world_seed : u32) -> Self {
-        // Make a new noise generator based on the seed
-        let noise = Noise::new(world_seed);
+    // rebuild a chunk from its blocks in index order (see LocalPos::index)
+    pub fn from_blocks(position : ChunkPos, blocks : Vec<BlockState>) -> Self {
+        assert_eq!(blocks.len(), CHUNK_VOLUME, "a chunk needs exactly {} blocks", CHUNK_VOLUME);
 
-        let mut cubes = Vec::new();
-
-        for x in 0..CHUNK_SIZE + 1 {
-            for y in 0..MAX_CHUNK_HEIGHT + 1 {
-                for z in 0..CHUNK_SIZE + 1 {
-                    // Original cube position without adjustments
-                    let cube_position = Vector3::new(
-                        position.x * CHUNK_SIZE as f32 + x as f32,
-                        position.y * MAX_CHUNK_HEIGHT as f32 + y as f32,
-                        position.z * CHUNK_SIZE as f32 + z as f32,
-                    );
-
-                    // Adjust position based on neighbor chunk (will only apply to cubes at the beginning edge of a chunk)
-                    let adjusted_position = match neighbor_chunk {
-                        Some(neighbor) if x == 0 && neighbor.position.x < position.x => {
-                            Vector3::new(cube_position.x - CHUNK_SIZE as f32, cube_position.y, cube_position.z)
-                        }
-                        Some(neighbor) if z == 0 && neighbor.position.z < position.z => {
-                            Vector3::new(cube_position.x, cube_position.y, cube_position.z - CHUNK_SIZE as f32)
-                        }
-                        _ => cube_position,
-                    };
+        Chunk {
+            position,
+            blocks,
+            dirty: true,
+            edited: false,
+        }
+    }
 
-                    let generated_noise = get_layered_noise(
-                        noise.get_base_noise(adjusted_position),
-                        0.3,
-                        noise.get_detail_noise(adjusted_position),
-                        0.7
-                    );
+    // generate the terrain for a chunk, the result only depends on the seed and the chunk's position
+    pub fn generate(position: ChunkPos, world_seed : u32, terrain : &TerrainBlocks) -> Self {
+        // Make a new noise generator based on the seed
+        let noise = Noise::new(world_seed);
 
-                    let cube_type = determine_cube_type(generated_noise, adjusted_position, y);
+        let mut chunk = Chunk::new(position);
 
-                    cubes.push(Cube::new(adjusted_position, cube_type));
+        for x in 0..CHUNK_SIZE {
+            for y in 0..MAX_CHUNK_HEIGHT {
+                for z in 0..CHUNK_SIZE {
+                    let local = LocalPos::new(x, y, z);
+                    chunk.blocks[local.index()] = generate_block(&noise, position.block_pos(local), terrain);
                 }
             }
         }
 
-        Chunk::new(position, cubes)
+        chunk
     }
 
-    // TODO these two are very ineffiecient
-    // check if this chunk contains a cube at the given position
-    pub fn has_cube(&self, cube_position : Vector3<f32>) -> bool {
-        return self.cubes.iter().any( |cube| cube.position == cube_position);
-    }
+    // get cube type at given position, positions outside the chunk are treated as air
+    pub fn get(&self, local : LocalPos) -> BlockState {
+        if !local.in_bounds() {
+            return BlockState::AIR;
+        }
 
-    // get cube type at given position
-    pub fn at(&self, cube_position : Vector3<f32>) -> CubeType {
-        let cube = self.cubes.iter().find(|cube| cube_position == cube.position);
+        self.blocks[local.index()]
+    }
 
-        match cube {
-            Some(found_cube) => found_cube._type,
-            None => CubeType::AIR,
+    // set cube type at given position, positions outside the chunk are ignored and return false
+    pub fn set(&mut self, local : LocalPos, _type : BlockState) -> bool {
+        if !local.in_bounds() {
+            return false;
         }
+
+        self.blocks[local.index()] = _type;
+        self.dirty = true;
+        self.edited = true;
+        true
+    }
+
+    // every block in index order, including air
+    pub fn block_data(&self) -> &[BlockState] {
+        &self.blocks
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    pub fn clear_dirty(&mut self) {
+        self.dirty = false;
     }
 
-    // set cube
-    pub fn set(&self, position : Vector3<f32>, _type : CubeType) {
-        let mut cube = self.cubes.iter().find(|cube| position == cube.position);
-        //cube.set_type(_type); todo error
+    pub fn is_edited(&self) -> bool {
+        self.edited
     }
+
+    // called once the chunk's edits have been saved
+    pub fn clear_edited(&mut self) {
+        self.edited = false;
+    }
+
+    // iterate over every block in the chunk that isn't air
+    pub fn blocks(&self) -> impl Iterator<Item = (LocalPos, BlockState)> + '_ {
+        self.blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, block)| block.id != BlockId::AIR)
+            .map(|(index, block)| (LocalPos::from_index(index), *block))
+    }
+
+    // position of a block in the world
+    pub fn block_pos(&self, local : LocalPos) -> BlockPos {
+        self.position.block_pos(local)
+    }
+}
+
+// the terrain block at a world position, which doesn't depend on the chunk asking for it
+pub fn generate_block(noise : &Noise, position : BlockPos, terrain : &TerrainBlocks) -> BlockState {
+    let generated_noise = get_layered_noise(
+        noise.get_base_noise(position),
+        0.3,
+        noise.get_detail_noise(position),
+        0.7
+    );
+
+    determine_block(generated_noise, position, position.local_pos().y, terrain)
 }
 
+#[cfg(test)]
+mod tests {
+    use crate::core::assets::Assets;
+    use crate::creation::block_registry::load_block_registry;
+    use crate::game_specs::TERRAIN_CHUNK_LAYER;
+    use super::*;
+
+    const SEED : u32 = 1234;
+
+    fn terrain() -> TerrainBlocks {
+        TerrainBlocks::from_registry(&load_block_registry(&Assets::built_in()).unwrap()).unwrap()
+    }
+
+    fn block_list(chunk : &Chunk) -> Vec<(LocalPos, BlockState)> {
+        chunk.blocks().collect()
+    }
+
+    // every block in the chunk is exactly the block the terrain has at that world position
+    fn assert_matches_terrain(chunk : &Chunk) {
+        let noise = Noise::new(SEED);
+        let terrain = terrain();
+
+        for index in 0..CHUNK_VOLUME {
+            let local = LocalPos::from_index(index);
+            assert_eq!(chunk.get(local), generate_block(&noise, chunk.block_pos(local), &terrain), "mismatch at {:?}", local);
+        }
+    }
+
+    #[test]
+    fn generation_is_deterministic() {
+        let position = ChunkPos::new(3, -1, -7);
+
+        let terrain = terrain();
+
+        assert_eq!(block_list(&Chunk::generate(position, SEED, &terrain)), block_list(&Chunk::generate(position, SEED, &terrain)));
+    }
+
+    #[test]
+    fn chunks_match_terrain_including_negative_positions() {
+        for position in [ChunkPos::new(0, -1, 0), ChunkPos::new(-1, -1, -1), ChunkPos::new(-5, -1, 4)] {
+            assert_matches_terrain(&Chunk::generate(position, SEED, &terrain()));
+        }
+    }
+
+    #[test]
+    fn terrain_has_grass_on_top() {
+        let terrain = terrain();
+        let chunk = Chunk::generate(ChunkPos::new(0, TERRAIN_CHUNK_LAYER, 0), SEED, &terrain);
+        let blocks = block_list(&chunk);
+
+        assert!(blocks.iter().any(|(_, block)| *block == terrain.grass));
+        assert!(blocks.iter().any(|(_, block)| *block == terrain.dirt));
+        assert!(blocks.iter().any(|(_, block)| *block == terrain.stone));
+
+        // grass only grows on the top layer
+        assert!(blocks.iter().all(|(local, block)| *block != terrain.grass || local.y == MAX_CHUNK_HEIGHT - 1));
+    }
+
+    #[test]
+    fn adjacent_chunks_line_up_at_borders() {
+        let noise = Noise::new(SEED);
+        let terrain = terrain();
+        let center = ChunkPos::new(-1, -1, 0);
+
+        for (dx, dz) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            let neighbor_position = center.offset(dx, 0, dz);
+
+            // generation order shouldn't matter
+            let neighbor_first = Chunk::generate(neighbor_position, SEED, &terrain);
+            let chunk = Chunk::generate(center, SEED, &terrain);
+            let neighbor = Chunk::generate(neighbor_position, SEED, &terrain);
+            assert_eq!(block_list(&neighbor_first), block_list(&neighbor));
+
+            // walk across the border, the blocks on both sides should continue the same terrain
+            for y in 0..MAX_CHUNK_HEIGHT {
+                for i in 0..CHUNK_SIZE {
+                    let (inside, outside) = match (dx, dz) {
+                        (1, 0) => (LocalPos::new(CHUNK_SIZE - 1, y, i), LocalPos::new(0, y, i)),
+                        (-1, 0) => (LocalPos::new(0, y, i), LocalPos::new(CHUNK_SIZE - 1, y, i)),
+                        (0, 1) => (LocalPos::new(i, y, CHUNK_SIZE - 1), LocalPos::new(i, y, 0)),
+                        _ => (LocalPos::new(i, y, 0), LocalPos::new(i, y, CHUNK_SIZE - 1)),
+                    };
 
+                    let inside_position = chunk.block_pos(inside);
+                    let outside_position = neighbor.block_pos(outside);
+
+                    // the two blocks are direct neighbors in the world, with no gap or overlap
+                    assert_eq!(inside_position.offset(dx, 0, dz), outside_position);
+
+                    assert_eq!(chunk.get(inside), generate_block(&noise, inside_position, &terrain));
+                    assert_eq!(neighbor.get(outside), generate_block(&noise, outside_position, &terrain));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+use cgmath::Vector3;
+use crate::creation::chunk_workers::{ChunkWorkers, WorkerResult};
+use crate::creation::cube::TerrainBlocks;
+use crate::creation::position::{BlockPos, ChunkPos};
+use crate::creation::storage::WorldStorage;
+use crate::creation::world::World;
+use crate::game_specs::TERRAIN_CHUNK_LAYER;
+
+// changes to the set of loaded chunks that the renderer needs to know about
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChunkEvent {
+    Loaded(ChunkPos),
+    Modified(ChunkPos),
+    Unloaded(ChunkPos),
+}
+
+// keeps the chunks around the camera loaded as it moves through the world
+pub struct ChunkManager {
+    // chunks within this many chunks of the camera are generated
+    load_radius : i32,
+    // chunks further than this are unloaded, the gap stops chunks on the border loading and unloading every frame
+    unload_radius : i32,
+    center : Option<ChunkPos>,
+    events : Vec<ChunkEvent>,
+    workers : ChunkWorkers,
+}
+
+impl ChunkManager {
+    pub fn new(load_radius : usize,
+               hysteresis : usize,
+               worker_threads : usize,
+               world_seed : u32,
+               terrain : TerrainBlocks,
+               storage : Option<Arc<WorldStorage>>) -> Self {
+        ChunkManager {
+            load_radius: load_radius as i32,
+            unload_radius: (load_radius + hysteresis) as i32,
+            center: None,
+            events: Vec::new(),
+            workers: ChunkWorkers::new(worker_threads, world_seed, terrain, storage),
+        }
+    }
+
+    // called every frame with the camera position
+    pub fn update(&mut self, world : &mut World, camera_position : Vector3<f32>) {
+        let mut center = BlockPos::from_world(camera_position).chunk_pos();
+        center.y = TERRAIN_CHUNK_LAYER;
+
+        // the set of chunks we want only changes when the camera crosses into another chunk
+        if self.center != Some(center) {
+            self.center = Some(center);
+
+            self.unload_distant_chunks(world, center);
+
+            // chunks that left the load radius before they were generated aren't needed anymore
+            let load_radius = self.load_radius;
+            self.workers.retain_requests(|position| distance(position, center) <= load_radius);
+
+            for position in self.missing_chunks(world, center) {
+                if world.restore_unloaded_edits(position) {
+                    self.events.push(ChunkEvent::Loaded(position));
+                } else {
+                    self.workers.request(position, priority(position, center));
+                }
+            }
+
+            self.workers.reprioritize(|position| priority(position, center));
+        }
+
+        let mut loaded : HashSet<ChunkPos> = self.events
+            .iter()
+            .filter_map(|event| match event {
+                ChunkEvent::Loaded(position) => Some(*position),
+                _ => None,
+            })
+            .collect();
+
+        for result in self.workers.finished() {
+            let chunk = match result {
+                WorkerResult::Loaded(chunk) => chunk,
+                WorkerResult::Saved(chunk) => {
+                    world.unloaded_chunk_saved(&chunk);
+                    continue;
+                }
+                // the edits stay in the world, so they're saved again along with it
+                WorkerResult::SaveFailed(chunk, error) => {
+                    eprintln!("Failed to save chunk {:?}, its edits are kept until the world is saved: {}", chunk.position, error);
+                    continue;
+                }
+            };
+            let position = chunk.position;
+
+            // the camera may have moved on while the chunk was being generated
+            if distance(position, center) > self.unload_radius || world.contains_chunk(position) {
+                continue;
+            }
+
+            world.insert_chunk(chunk);
+            loaded.insert(position);
+            self.events.push(ChunkEvent::Loaded(position));
+        }
+
+        // newly loaded chunks are dirty too, but they've already been reported
+        for position in world.take_dirty_chunks() {
+            if !loaded.contains(&position) {
+                self.events.push(ChunkEvent::Modified(position));
+            }
+        }
+    }
+
+    // events since the last call, in the order they happened
+    pub fn take_events(&mut self) -> Vec<ChunkEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    fn unload_distant_chunks(&mut self, world : &mut World, center : ChunkPos) {
+        let distant : Vec<ChunkPos> = world
+            .chunk_positions()
+            .filter(|position| distance(*position, center) > self.unload_radius)
+            .collect();
+
+        // edited chunks are written by the workers so the region files aren't rewritten on this thread
+        for position in distant {
+            if let Some(chunk) = world.unload_chunk(position) {
+                self.workers.save(chunk);
+            }
+            self.events.push(ChunkEvent::Unloaded(position));
+        }
+    }
+
+    // positions within the load radius that have no chunk and haven't been requested yet
+    fn missing_chunks(&self, world : &World, center : ChunkPos) -> Vec<ChunkPos> {
+        let mut missing = Vec::new();
+
+        for x in -self.load_radius..=self.load_radius {
+            for z in -self.load_radius..=self.load_radius {
+                let position = center.offset(x, 0, z);
+
+                if !world.contains_chunk(position) && !self.workers.is_pending(position) {
+                    missing.push(position);
+                }
+            }
+        }
+
+        missing
+    }
+}
+
+// number of chunks between two chunk columns, measured along the furthest horizontal axis
+fn distance(a : ChunkPos, b : ChunkPos) -> i32 {
+    (a.x - b.x).abs().max((a.z - b.z).abs())
+}
+
+// chunks closest to the camera are generated first
+fn priority(position : ChunkPos, center : ChunkPos) -> i32 {
+    let dx = position.x - center.x;
+    let dz = position.z - center.z;
+    dx * dx + dz * dz
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::{Duration, Instant};
+    use crate::core::assets::Assets;
+    use crate::creation::block_registry::{load_block_registry, BlockState};
+    use crate::game_specs::{CHUNK_SIZE, MAX_CHUNK_HEIGHT};
+    use super::*;
+
+    const SEED : u32 = 99;
+
+    fn manager(world : &World) -> ChunkManager {
+        let terrain = TerrainBlocks::from_registry(&load_block_registry(&Assets::built_in()).unwrap()).unwrap();
+        ChunkManager::new(1, 1, 2, SEED, terrain, world.storage())
+    }
+
+    // a block the terrain never generates
+    fn slab() -> BlockState {
+        BlockState::new(load_block_registry(&Assets::built_in()).unwrap().id("stone_slab").unwrap())
+    }
+
+    // the camera in the middle of a chunk column
+    fn camera(x : i32) -> Vector3<f32> {
+        let middle = (CHUNK_SIZE / 2) as f32;
+        Vector3::new(x as f32 * CHUNK_SIZE as f32 + middle, 0.0, middle)
+    }
+
+    fn loaded_columns(world : &World) -> Vec<(i32, i32)> {
+        let mut columns : Vec<_> = world.chunk_positions().map(|position| (position.x, position.z)).collect();
+        columns.sort();
+        columns
+    }
+
+    fn columns(xs : std::ops::RangeInclusive<i32>) -> Vec<(i32, i32)> {
+        xs.flat_map(|x| (-1..=1).map(move |z| (x, z))).collect()
+    }
+
+    // update until the condition holds, the workers finish in their own time
+    fn update_until<F : Fn(&World) -> bool>(manager : &mut ChunkManager, world : &mut World, x : i32, done : F) {
+        let start = Instant::now();
+
+        while !done(world) {
+            assert!(start.elapsed() < Duration::from_secs(20), "chunks didn't finish loading");
+            manager.update(world, camera(x));
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn chunks_load_within_the_radius_and_unload_past_the_hysteresis() {
+        let mut world = World::with_seed(SEED);
+        let mut manager = manager(&world);
+
+        update_until(&mut manager, &mut world, 0, |world| loaded_columns(world) == columns(-1..=1));
+        let events = manager.take_events();
+        assert_eq!(events.iter().filter(|event| matches!(event, ChunkEvent::Loaded(_))).count(), 9);
+        assert!(world.chunk_positions().all(|position| position.y == TERRAIN_CHUNK_LAYER));
+
+        // one chunk over, the column that's now two away is kept
+        update_until(&mut manager, &mut world, 1, |world| loaded_columns(world) == columns(-1..=2));
+        assert!(!manager.take_events().iter().any(|event| matches!(event, ChunkEvent::Unloaded(_))));
+
+        // another one over and it's past the unload radius
+        update_until(&mut manager, &mut world, 2, |world| loaded_columns(world) == columns(0..=3));
+        let unloaded = manager.take_events().iter().filter(|event| matches!(event, ChunkEvent::Unloaded(_))).count();
+        assert_eq!(unloaded, 3);
+    }
+
+    #[test]
+    fn closer_chunks_have_higher_priority() {
+        let center = ChunkPos::new(3, TERRAIN_CHUNK_LAYER, -2);
+        let mut positions = vec![center.offset(2, 0, 2), center.offset(0, 0, -1), center, center.offset(-2, 0, 1)];
+        positions.sort_by_key(|position| priority(*position, center));
+
+        assert_eq!(positions, vec![center, center.offset(0, 0, -1), center.offset(-2, 0, 1), center.offset(2, 0, 2)]);
+    }
+
+    #[test]
+    fn edits_to_unloaded_chunks_are_saved_by_the_workers() {
+        let directory : PathBuf = std::env::temp_dir().join(format!("rustworld-manager-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+
+        let mut world = World::with_seed(SEED);
+        world.save(&directory).unwrap();
+        let storage = world.storage().unwrap();
+        let mut manager = manager(&world);
+
+        update_until(&mut manager, &mut world, 0, |world| loaded_columns(world) == columns(-1..=1));
+        let block = BlockPos::new(-5, TERRAIN_CHUNK_LAYER * MAX_CHUNK_HEIGHT as i32 + 3, 3);
+        assert!(world.set_block(block, slab()));
+
+        // far enough away for the edited chunk to unload and be written to its region
+        update_until(&mut manager, &mut world, 3, |_| {
+            storage.load_chunk(block.chunk_pos()).unwrap().is_some_and(|chunk| chunk.get(block.local_pos()) == slab())
+        });
+        assert_eq!(world.get_block(block), None);
+
+        // and back again, the chunk comes back with its edit
+        update_until(&mut manager, &mut world, 0, |world| world.get_block(block).is_some());
+        assert_eq!(world.get_block(block), Some(slab()));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
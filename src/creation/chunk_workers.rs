@@ -0,0 +1,360 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet, VecDeque};
+use std::io;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::JoinHandle;
+use crate::creation::chunk::Chunk;
+use crate::creation::cube::TerrainBlocks;
+use crate::creation::position::ChunkPos;
+use crate::creation::storage::WorldStorage;
+
+// a pool of threads loading, generating and saving chunks off the main thread
+pub struct ChunkWorkers {
+    shared : Arc<Shared>,
+    results : Receiver<WorkerResult>,
+    threads : Vec<JoinHandle<()>>,
+}
+
+pub enum WorkerResult {
+    // a requested chunk, loaded from storage or generated
+    Loaded(Chunk),
+    // an unloaded chunk that has been written to storage
+    Saved(Chunk),
+    SaveFailed(Chunk, io::Error),
+}
+
+// state shared between the main thread and the workers
+struct Shared {
+    queue : Mutex<Queue>,
+    // signalled whenever a request or save is added or the pool shuts down
+    available : Condvar,
+}
+
+struct Queue {
+    requests : BinaryHeap<Request>,
+    // requested chunks that no worker has started on yet
+    waiting : HashSet<ChunkPos>,
+    // chunks a worker is currently generating
+    in_flight : HashSet<ChunkPos>,
+    // edited chunks waiting to be written, in the order they were unloaded
+    saves : VecDeque<Chunk>,
+    shutdown : bool,
+}
+
+enum Job {
+    Load(ChunkPos),
+    Save(Chunk),
+}
+
+// the request with the lowest priority value is handed out first
+#[derive(PartialEq, Eq)]
+struct Request {
+    priority : i32,
+    position : ChunkPos,
+}
+
+impl Ord for Request {
+    fn cmp(&self, other : &Self) -> Ordering {
+        other.priority.cmp(&self.priority)
+    }
+}
+
+impl PartialOrd for Request {
+    fn partial_cmp(&self, other : &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl ChunkWorkers {
+    // chunks saved in storage are loaded from it, everything else is generated from the seed
+    pub fn new(thread_count : usize,
+               world_seed : u32,
+               terrain : TerrainBlocks,
+               storage : Option<Arc<WorldStorage>>) -> Self {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue::new()),
+            available: Condvar::new(),
+        });
+
+        let (sender, results) = channel();
+
+        let threads = (0..thread_count.max(1))
+            .map(|_| {
+                let shared = Arc::clone(&shared);
+                let sender = sender.clone();
+                let storage = storage.clone();
+                std::thread::spawn(move || run_worker(shared, sender, world_seed, terrain, storage))
+            })
+            .collect();
+
+        ChunkWorkers {
+            shared,
+            results,
+            threads,
+        }
+    }
+
+    // queue a chunk for generation, lower priorities are generated first
+    pub fn request(&self, position : ChunkPos, priority : i32) {
+        if self.shared.queue.lock().unwrap().request(position, priority) {
+            self.shared.available.notify_one();
+        }
+    }
+
+    pub fn is_pending(&self, position : ChunkPos) -> bool {
+        self.shared.queue.lock().unwrap().is_pending(position)
+    }
+
+    // cancel every waiting or in-flight request the predicate rejects
+    pub fn retain_requests<F : Fn(ChunkPos) -> bool>(&self, keep : F) {
+        self.shared.queue.lock().unwrap().retain(keep);
+    }
+
+    // recalculate the priority of every waiting request, e.g. after the camera has moved
+    pub fn reprioritize<F : Fn(ChunkPos) -> i32>(&self, priority : F) {
+        self.shared.queue.lock().unwrap().reprioritize(priority);
+    }
+
+    // write an unloaded chunk to storage, saves are done before any waiting requests and before the pool shuts down
+    pub fn save(&self, chunk : Chunk) {
+        self.shared.queue.lock().unwrap().saves.push_back(chunk);
+        self.shared.available.notify_one();
+    }
+
+    // chunks that have finished loading or saving since the last call
+    pub fn finished(&self) -> impl Iterator<Item = WorkerResult> + '_ {
+        self.results.try_iter()
+    }
+}
+
+impl Drop for ChunkWorkers {
+    fn drop(&mut self) {
+        self.shared.queue.lock().unwrap().shutdown = true;
+        self.shared.available.notify_all();
+
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Queue {
+    fn new() -> Self {
+        Queue {
+            requests: BinaryHeap::new(),
+            waiting: HashSet::new(),
+            in_flight: HashSet::new(),
+            saves: VecDeque::new(),
+            shutdown: false,
+        }
+    }
+
+    // false if the chunk has already been requested
+    fn request(&mut self, position : ChunkPos, priority : i32) -> bool {
+        if self.is_pending(position) {
+            return false;
+        }
+
+        self.waiting.insert(position);
+        self.requests.push(Request { priority, position });
+        true
+    }
+
+    fn is_pending(&self, position : ChunkPos) -> bool {
+        self.waiting.contains(&position) || self.in_flight.contains(&position)
+    }
+
+    // cancelled requests stay in the heap until they're popped, they're skipped because they're no longer waiting
+    fn retain<F : Fn(ChunkPos) -> bool>(&mut self, keep : F) {
+        self.waiting.retain(|position| keep(*position));
+        self.in_flight.retain(|position| keep(*position));
+    }
+
+    fn reprioritize<F : Fn(ChunkPos) -> i32>(&mut self, priority : F) {
+        self.requests = self.waiting
+            .iter()
+            .map(|position| Request { priority: priority(*position), position: *position })
+            .collect();
+    }
+
+    // saves first, then the most urgent request. Once shutting down only the saves are left to do
+    fn next_job(&mut self) -> Option<Job> {
+        if let Some(chunk) = self.saves.pop_front() {
+            return Some(Job::Save(chunk));
+        }
+
+        if self.shutdown {
+            return None;
+        }
+
+        self.next().map(Job::Load)
+    }
+
+    // the most urgent request that hasn't been cancelled, which is now in flight
+    fn next(&mut self) -> Option<ChunkPos> {
+        while let Some(request) = self.requests.pop() {
+            if self.waiting.remove(&request.position) {
+                self.in_flight.insert(request.position);
+                return Some(request.position);
+            }
+        }
+
+        None
+    }
+
+    // true if the chunk is still wanted now that it's been generated
+    fn finish(&mut self, position : ChunkPos) -> bool {
+        self.in_flight.remove(&position)
+    }
+}
+
+fn run_worker(shared : Arc<Shared>,
+              results : Sender<WorkerResult>,
+              world_seed : u32,
+              terrain : TerrainBlocks,
+              storage : Option<Arc<WorldStorage>>) {
+    loop {
+        // wait for a save or a request that hasn't been cancelled
+        let job = {
+            let mut queue = shared.queue.lock().unwrap();
+
+            loop {
+                match queue.next_job() {
+                    Some(job) => break job,
+                    None if queue.shutdown => return,
+                    None => queue = shared.available.wait(queue).unwrap(),
+                }
+            }
+        };
+
+        let position = match job {
+            Job::Load(position) => position,
+            Job::Save(chunk) => {
+                // only chunks with somewhere to go are sent to be saved
+                let result = match storage.as_ref().map(|storage| storage.save_chunks([&chunk])) {
+                    Some(Err(error)) => WorkerResult::SaveFailed(chunk, error),
+                    _ => WorkerResult::Saved(chunk),
+                };
+
+                if results.send(result).is_err() {
+                    return;
+                }
+                continue;
+            }
+        };
+
+        let saved_chunk = storage.as_ref().and_then(|storage| {
+            storage.load_chunk(position).unwrap_or_else(|error| {
+                eprintln!("Failed to load chunk {:?}, generating it instead: {}", position, error);
+                None
+            })
+        });
+
+        let chunk = saved_chunk.unwrap_or_else(|| Chunk::generate(position, world_seed, &terrain));
+
+        // throw the chunk away if it was cancelled while it was being generated
+        let still_wanted = shared.queue.lock().unwrap().finish(position);
+
+        if still_wanted && results.send(WorkerResult::Loaded(chunk)).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(x : i32) -> ChunkPos {
+        ChunkPos::new(x, 0, 0)
+    }
+
+    fn drain(queue : &mut Queue) -> Vec<i32> {
+        std::iter::from_fn(|| queue.next()).map(|position| position.x).collect()
+    }
+
+    #[test]
+    fn requests_are_handed_out_by_priority_once() {
+        let mut queue = Queue::new();
+
+        for (x, priority) in [(1, 4), (2, 1), (3, 9), (4, 0)] {
+            assert!(queue.request(chunk(x), priority));
+        }
+        assert!(!queue.request(chunk(3), 0));
+
+        assert_eq!(drain(&mut queue), vec![4, 2, 1, 3]);
+        assert!(queue.is_pending(chunk(1)));
+        assert!(!queue.request(chunk(1), 0));
+    }
+
+    #[test]
+    fn cancelled_requests_are_skipped() {
+        let mut queue = Queue::new();
+
+        for x in 0..6 {
+            queue.request(chunk(x), x);
+        }
+        queue.retain(|position| position.x % 2 == 0);
+
+        assert!(!queue.is_pending(chunk(1)));
+        assert_eq!(drain(&mut queue), vec![0, 2, 4]);
+
+        // a cancelled chunk can be asked for again
+        assert!(queue.request(chunk(1), 0));
+        assert_eq!(drain(&mut queue), vec![1]);
+    }
+
+    #[test]
+    fn reprioritizing_changes_the_order() {
+        let mut queue = Queue::new();
+
+        for x in 0..5 {
+            queue.request(chunk(x), x);
+        }
+        assert_eq!(queue.next(), Some(chunk(0)));
+
+        // the camera moved to chunk 4
+        queue.reprioritize(|position| (position.x - 4).abs());
+        assert_eq!(drain(&mut queue), vec![4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn saves_come_first_and_outlast_shutdown() {
+        let mut queue = Queue::new();
+        queue.request(chunk(0), 0);
+        queue.saves.push_back(Chunk::new(chunk(7)));
+
+        let job = |queue : &mut Queue| match queue.next_job() {
+            Some(Job::Load(position)) => Some(("load", position.x)),
+            Some(Job::Save(chunk)) => Some(("save", chunk.position.x)),
+            None => None,
+        };
+
+        assert_eq!(job(&mut queue), Some(("save", 7)));
+        assert_eq!(job(&mut queue), Some(("load", 0)));
+
+        queue.request(chunk(1), 0);
+        queue.saves.push_back(Chunk::new(chunk(8)));
+        queue.shutdown = true;
+        assert_eq!(job(&mut queue), Some(("save", 8)));
+        assert_eq!(job(&mut queue), None);
+    }
+
+    #[test]
+    fn results_of_cancelled_requests_are_dropped() {
+        let mut queue = Queue::new();
+        queue.request(chunk(0), 0);
+        queue.request(chunk(1), 1);
+
+        let first = queue.next().unwrap();
+        let second = queue.next().unwrap();
+
+        // cancelled while a worker was generating it
+        queue.retain(|position| position != second);
+
+        assert!(queue.finish(first));
+        assert!(!queue.finish(second));
+        assert!(!queue.is_pending(first) && !queue.is_pending(second));
+    }
+}
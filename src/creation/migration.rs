@@ -0,0 +1,143 @@
+use std::io;
+use serde_json::Value;
+
+// Saved worlds are upgraded to the current format one version at a time as they're loaded.
+// To change the save format, bump FORMAT_VERSION and add a Migration from the previous version to MIGRATIONS.
+//
+// Version history:
+//   1  chunks are bare block ids, always 16 x 10 x 16
+//   2  chunks start with a header recording their format version and dimensions
+//   3  each block's properties are stored after the block ids
+
+pub const FORMAT_VERSION : u32 = 3;
+
+// a saved chunk before it is turned back into a Chunk
+pub struct ChunkRecord {
+    pub version : u32,
+    // width, height and depth of the chunk in blocks
+    pub size : (usize, usize, usize),
+    // one block id per block, x varies fastest, then z, then y
+    pub blocks : Vec<u8>,
+    // one properties byte per block in the same order, see BlockDefinition::property
+    pub properties : Vec<u8>,
+}
+
+// converts level and chunk data saved by version `from` to version `from + 1`
+struct Migration {
+    from : u32,
+    level : fn(&mut Value),
+    chunk : fn(ChunkRecord) -> ChunkRecord,
+}
+
+const MIGRATIONS : [Migration; 2] = [
+    Migration { from: 1, level: unchanged_level, chunk: record_chunk_size },
+    Migration { from: 2, level: unchanged_level, chunk: add_block_properties },
+];
+
+// check a saved version can be read by this build
+pub fn check_version(version : u32) -> io::Result<()> {
+    if version > FORMAT_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("saved by a newer version of RustWorld (format {}), this build can only read up to format {}",
+                    version, FORMAT_VERSION)
+        ));
+    }
+
+    if version == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid save format version 0"));
+    }
+
+    Ok(())
+}
+
+// bring the contents of level.json up to the current format
+pub fn upgrade_level(mut level : Value) -> io::Result<Value> {
+    let version = level
+        .get("version")
+        .and_then(Value::as_u64)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "level.json has no format version"))? as u32;
+
+    check_version(version)?;
+
+    for migration in MIGRATIONS.iter().filter(|migration| migration.from >= version) {
+        (migration.level)(&mut level);
+    }
+
+    level["version"] = Value::from(FORMAT_VERSION);
+    Ok(level)
+}
+
+// bring a saved chunk up to the current format
+pub fn upgrade_chunk(mut chunk : ChunkRecord) -> io::Result<ChunkRecord> {
+    check_version(chunk.version)?;
+    let version = chunk.version;
+
+    for migration in MIGRATIONS.iter().filter(|migration| migration.from >= version) {
+        chunk = (migration.chunk)(chunk);
+        chunk.version = migration.from + 1;
+    }
+
+    Ok(chunk)
+}
+
+fn unchanged_level(_level : &mut Value) {}
+
+// version 1 chunks didn't record their size, but it was always 16 x 10 x 16
+fn record_chunk_size(chunk : ChunkRecord) -> ChunkRecord {
+    ChunkRecord {
+        size: (16, 10, 16),
+        ..chunk
+    }
+}
+
+// blocks had no properties before version 3, which is the same as every property having its first value
+fn add_block_properties(chunk : ChunkRecord) -> ChunkRecord {
+    ChunkRecord {
+        properties: vec![0; chunk.blocks.len()],
+        ..chunk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    fn record(version : u32, size : (usize, usize, usize), blocks : Vec<u8>) -> ChunkRecord {
+        ChunkRecord { version, size, blocks, properties: Vec::new() }
+    }
+
+    #[test]
+    fn version_1_chunks_get_their_size_and_properties() {
+        let blocks : Vec<u8> = (0..16 * 10 * 16).map(|index| (index % 7) as u8).collect();
+        let chunk = upgrade_chunk(record(1, (0, 0, 0), blocks.clone())).unwrap();
+
+        assert_eq!(chunk.version, FORMAT_VERSION);
+        assert_eq!(chunk.size, (16, 10, 16));
+        assert_eq!(chunk.blocks, blocks);
+        assert_eq!(chunk.properties, vec![0; blocks.len()]);
+    }
+
+    #[test]
+    fn version_2_chunks_keep_their_size_and_get_properties() {
+        let chunk = upgrade_chunk(record(2, (8, 4, 8), vec![1; 8 * 4 * 8])).unwrap();
+
+        assert_eq!(chunk.version, FORMAT_VERSION);
+        assert_eq!(chunk.size, (8, 4, 8));
+        assert_eq!(chunk.properties, vec![0; 8 * 4 * 8]);
+    }
+
+    #[test]
+    fn unknown_versions_are_rejected() {
+        assert!(check_version(FORMAT_VERSION).is_ok());
+        assert_eq!(check_version(0).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(check_version(FORMAT_VERSION + 1).unwrap_err().kind(), io::ErrorKind::Unsupported);
+
+        assert!(upgrade_chunk(record(0, (16, 10, 16), Vec::new())).is_err());
+        assert!(upgrade_chunk(record(FORMAT_VERSION + 1, (16, 10, 16), Vec::new())).is_err());
+        assert!(upgrade_level(json!({ "version": FORMAT_VERSION + 1, "seed": 1 })).is_err());
+        assert!(upgrade_level(json!({ "seed": 1 })).is_err());
+        assert_eq!(upgrade_level(json!({ "version": 1, "seed": 1 })).unwrap()["version"], json!(FORMAT_VERSION));
+    }
+}
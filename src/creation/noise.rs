@@ -1,4 +1,4 @@
-use cgmath::Vector3;
+use crate::creation::position::BlockPos;
 use noise::{NoiseFn, OpenSimplex};
 
 pub struct Noise {
@@ -14,19 +14,19 @@ impl Noise {
         }
     }
 
-    pub fn get_base_noise(&self, cube_position : Vector3<f32>) -> f64 {
+    pub fn get_base_noise(&self, cube_position : BlockPos) -> f64 {
         self.perlin.get([
-            (cube_position.x / 50.0) as f64,
-            (cube_position.y / 50.0) as f64,
-            (cube_position.z / 50.0) as f64,
+            cube_position.x as f64 / 50.0,
+            cube_position.y as f64 / 50.0,
+            cube_position.z as f64 / 50.0,
         ])
     }
 
-    pub fn get_detail_noise(&self, cube_position : Vector3<f32>) -> f64 {
+    pub fn get_detail_noise(&self, cube_position : BlockPos) -> f64 {
         self.perlin.get([
-            (cube_position.x / 10.0) as f64,
-            (cube_position.y / 10.0) as f64,
-            (cube_position.z / 10.0) as f64,
+            cube_position.x as f64 / 10.0,
+            cube_position.y as f64 / 10.0,
+            cube_position.z as f64 / 10.0,
         ])
     }
 }
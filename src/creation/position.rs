@@ -0,0 +1,189 @@
+use cgmath::Vector3;
+use crate::game_specs::{CHUNK_SIZE, MAX_CHUNK_HEIGHT};
+
+// integer coordinates of a block in the world
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BlockPos {
+    pub x : i32,
+    pub y : i32,
+    pub z : i32,
+}
+
+// integer coordinates of a chunk, in chunk units (one step is a whole chunk)
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ChunkPos {
+    pub x : i32,
+    pub y : i32,
+    pub z : i32,
+}
+
+// integer block coordinates relative to the corner of a chunk
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LocalPos {
+    pub x : usize,
+    pub y : usize,
+    pub z : usize,
+}
+
+impl BlockPos {
+    pub fn new(x : i32, y : i32, z : i32) -> Self {
+        BlockPos { x, y, z }
+    }
+
+    // the block containing a point in world space (cubes are centred on whole numbers)
+    pub fn from_world(position : Vector3<f32>) -> Self {
+        BlockPos {
+            x: position.x.round() as i32,
+            y: position.y.round() as i32,
+            z: position.z.round() as i32,
+        }
+    }
+
+    // centre of the block in world space
+    pub fn to_vec3(self) -> Vector3<f32> {
+        Vector3::new(self.x as f32, self.y as f32, self.z as f32)
+    }
+
+    pub fn offset(&self, dx : i32, dy : i32, dz : i32) -> Self {
+        BlockPos::new(self.x + dx, self.y + dy, self.z + dz)
+    }
+
+    // the chunk that owns this block, rounding towards negative infinity so negative coordinates work
+    pub fn chunk_pos(&self) -> ChunkPos {
+        ChunkPos {
+            x: self.x.div_euclid(CHUNK_SIZE as i32),
+            y: self.y.div_euclid(MAX_CHUNK_HEIGHT as i32),
+            z: self.z.div_euclid(CHUNK_SIZE as i32),
+        }
+    }
+
+    // position of this block within its chunk
+    pub fn local_pos(&self) -> LocalPos {
+        LocalPos {
+            x: self.x.rem_euclid(CHUNK_SIZE as i32) as usize,
+            y: self.y.rem_euclid(MAX_CHUNK_HEIGHT as i32) as usize,
+            z: self.z.rem_euclid(CHUNK_SIZE as i32) as usize,
+        }
+    }
+}
+
+impl ChunkPos {
+    pub fn new(x : i32, y : i32, z : i32) -> Self {
+        ChunkPos { x, y, z }
+    }
+
+    pub fn offset(&self, dx : i32, dy : i32, dz : i32) -> Self {
+        ChunkPos::new(self.x + dx, self.y + dy, self.z + dz)
+    }
+
+    // the six chunks sharing a face with this one
+    pub fn neighbors(&self) -> [ChunkPos; 6] {
+        [
+            self.offset(1, 0, 0),
+            self.offset(-1, 0, 0),
+            self.offset(0, 1, 0),
+            self.offset(0, -1, 0),
+            self.offset(0, 0, 1),
+            self.offset(0, 0, -1),
+        ]
+    }
+
+    // world position of the block at local (0, 0, 0)
+    pub fn origin(&self) -> BlockPos {
+        BlockPos {
+            x: self.x * CHUNK_SIZE as i32,
+            y: self.y * MAX_CHUNK_HEIGHT as i32,
+            z: self.z * CHUNK_SIZE as i32,
+        }
+    }
+
+    // world position of a block inside this chunk
+    pub fn block_pos(&self, local : LocalPos) -> BlockPos {
+        self.origin().offset(local.x as i32, local.y as i32, local.z as i32)
+    }
+}
+
+impl LocalPos {
+    pub fn new(x : usize, y : usize, z : usize) -> Self {
+        LocalPos { x, y, z }
+    }
+
+    // inverse of LocalPos::index
+    pub fn from_index(index : usize) -> Self {
+        LocalPos {
+            x: index % CHUNK_SIZE,
+            y: index / (CHUNK_SIZE * CHUNK_SIZE),
+            z: (index / CHUNK_SIZE) % CHUNK_SIZE,
+        }
+    }
+
+    // position of this block in the chunk's block array (x varies fastest, then z, then y)
+    pub fn index(&self) -> usize {
+        (self.y * CHUNK_SIZE + self.z) * CHUNK_SIZE + self.x
+    }
+
+    pub fn in_bounds(&self) -> bool {
+        self.x < CHUNK_SIZE && self.y < MAX_CHUNK_HEIGHT && self.z < CHUNK_SIZE
+    }
+
+    // offset this position by the given amount, or None if the result leaves the chunk
+    pub fn offset(&self, dx : i32, dy : i32, dz : i32) -> Option<LocalPos> {
+        let x = self.x as i32 + dx;
+        let y = self.y as i32 + dy;
+        let z = self.z as i32 + dz;
+
+        if x < 0 || y < 0 || z < 0 {
+            return None;
+        }
+
+        let local = LocalPos::new(x as usize, y as usize, z as usize);
+        if local.in_bounds() { Some(local) } else { None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(x : i32) -> (i32, usize) {
+        let position = BlockPos::new(x, 0, 0);
+        (position.chunk_pos().x, position.local_pos().x)
+    }
+
+    #[test]
+    fn negative_blocks_round_down_to_their_chunk() {
+        let size = CHUNK_SIZE as i32;
+
+        assert_eq!(split(0), (0, 0));
+        assert_eq!(split(size - 1), (0, CHUNK_SIZE - 1));
+        assert_eq!(split(size), (1, 0));
+        assert_eq!(split(-1), (-1, CHUNK_SIZE - 1));
+        assert_eq!(split(-size), (-1, 0));
+        assert_eq!(split(-size - 1), (-2, CHUNK_SIZE - 1));
+
+        let below = BlockPos::new(0, -1, 0);
+        assert_eq!((below.chunk_pos().y, below.local_pos().y), (-1, MAX_CHUNK_HEIGHT - 1));
+    }
+
+    #[test]
+    fn chunk_and_local_positions_give_back_the_block() {
+        for x in -40..40 {
+            for (y, z) in [(-11, 7), (-1, -17), (0, 0), (9, -16), (25, 33)] {
+                let position = BlockPos::new(x, y, z);
+                assert_eq!(position.chunk_pos().block_pos(position.local_pos()), position);
+            }
+        }
+    }
+
+    #[test]
+    fn local_indexes_round_trip() {
+        for index in [0, 1, CHUNK_SIZE, CHUNK_SIZE * CHUNK_SIZE, CHUNK_SIZE * CHUNK_SIZE * MAX_CHUNK_HEIGHT - 1] {
+            assert_eq!(LocalPos::from_index(index).index(), index);
+        }
+
+        let corner = LocalPos::new(CHUNK_SIZE - 1, 0, CHUNK_SIZE - 1);
+        assert_eq!(corner.offset(1, 0, 0), None);
+        assert_eq!(corner.offset(0, -1, 0), None);
+        assert_eq!(corner.offset(-1, 1, 0), Some(LocalPos::new(CHUNK_SIZE - 2, 1, CHUNK_SIZE - 1)));
+    }
+}
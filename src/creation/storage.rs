@@ -0,0 +1,426 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use serde::{Deserialize, Serialize};
+use crate::creation::chunk::{Chunk, CHUNK_VOLUME};
+use crate::creation::block_registry::{BlockId, BlockState};
+use crate::creation::migration::{ChunkRecord, FORMAT_VERSION, upgrade_chunk, upgrade_level};
+use crate::creation::position::{ChunkPos, LocalPos};
+use crate::game_specs::{CHUNK_SIZE, MAX_CHUNK_HEIGHT};
+
+// On disk a world is a directory containing:
+//   level.json           seed, spawn point and format version
+//   regions/r.X.Y.Z.bin  the saved chunks of one REGION_SIZE x 1 x REGION_SIZE block of chunks
+//
+// A region file starts with REGION_MAGIC, followed by one (offset, length) entry per chunk in the region
+// (little-endian u32s, ordered x then z) and then the zlib-compressed chunks themselves. An offset of 0 means
+// the chunk has never been saved and should be generated instead.
+//
+// Each chunk decompresses to CHUNK_MAGIC, its format version (u32), its width, height and depth (u16s) and then
+// one block id per block, then one properties byte per block. Block ids are the ids in blocks.json, an id that's no
+// longer defined is kept as it is and treated like air until a block with that id comes back. Chunks and levels
+// from older versions are upgraded by creation::migration.
+
+pub const REGION_SIZE : i32 = 32;
+
+const LEVEL_FILE : &str = "level.json";
+const REGION_DIRECTORY : &str = "regions";
+const REGION_MAGIC : &[u8; 4] = b"RWRG";
+const CHUNK_MAGIC : &[u8; 4] = b"RWCH";
+const CHUNK_HEADER_SIZE : usize = CHUNK_MAGIC.len() + 4 + 3 * 2;
+const REGION_CHUNKS : usize = (REGION_SIZE * REGION_SIZE) as usize;
+const HEADER_SIZE : usize = REGION_MAGIC.len() + REGION_CHUNKS * 8;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LevelData {
+    pub version : u32,
+    pub seed : u32,
+    pub spawn : [f32; 3],
+}
+
+// the directory a world is saved in
+pub struct WorldStorage {
+    directory : PathBuf,
+    // held while a region file is rewritten, so chunks saved from different threads don't overwrite each other
+    writing : Mutex<()>,
+}
+
+// coordinates of a region file, in region units
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct RegionPos {
+    x : i32,
+    y : i32,
+    z : i32,
+}
+
+impl WorldStorage {
+    // use the given directory for saving, creating it if it doesn't exist
+    pub fn open(directory : &Path) -> io::Result<Self> {
+        fs::create_dir_all(directory.join(REGION_DIRECTORY))?;
+
+        Ok(WorldStorage {
+            directory: directory.to_path_buf(),
+            writing: Mutex::new(()),
+        })
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    // true if a world has been saved in the directory
+    pub fn has_level(directory : &Path) -> bool {
+        directory.join(LEVEL_FILE).is_file()
+    }
+
+    // read level.json, upgrading it if it was saved by an older version
+    pub fn read_level(&self) -> io::Result<LevelData> {
+        let file = File::open(self.directory.join(LEVEL_FILE))?;
+        let level = serde_json::from_reader(BufReader::new(file)).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        serde_json::from_value(upgrade_level(level)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn write_level(&self, level : &LevelData) -> io::Result<()> {
+        let data = serde_json::to_vec_pretty(level).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        write_atomically(&self.directory.join(LEVEL_FILE), &data)
+    }
+
+    // read a chunk from its region file, or None if it has never been saved
+    pub fn load_chunk(&self, position : ChunkPos) -> io::Result<Option<Chunk>> {
+        let (region, index) = region_of(position);
+        let path = self.region_path(region);
+
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        let mut file = File::open(&path)?;
+        let entries = read_header(&mut file)?;
+        let (offset, length) = entries[index];
+
+        if offset == 0 {
+            return Ok(None);
+        }
+
+        // a corrupt header mustn't send the read outside the file or make a huge allocation
+        check_entry(offset, length, file.metadata()?.len())?;
+
+        let mut data = vec![0; length as usize];
+        file.seek(SeekFrom::Start(offset as u64))?;
+        file.read_exact(&mut data)?;
+
+        decode_chunk(position, &data).map(Some)
+    }
+
+    // write chunks into their region files, replacing any earlier copies
+    pub fn save_chunks<'a, I : IntoIterator<Item = &'a Chunk>>(&self, chunks : I) -> io::Result<()> {
+        let mut regions : HashMap<RegionPos, Vec<&Chunk>> = HashMap::new();
+
+        for chunk in chunks {
+            regions.entry(region_of(chunk.position).0).or_default().push(chunk);
+        }
+
+        let _writing = self.writing.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        for (region, chunks) in regions {
+            let path = self.region_path(region);
+            let mut payloads = if path.is_file() { read_region(&path)? } else { vec![None; REGION_CHUNKS] };
+
+            for chunk in chunks {
+                payloads[region_of(chunk.position).1] = Some(encode_chunk(chunk)?);
+            }
+
+            write_region(&path, &payloads)?;
+        }
+
+        Ok(())
+    }
+
+    fn region_path(&self, region : RegionPos) -> PathBuf {
+        self.directory
+            .join(REGION_DIRECTORY)
+            .join(format!("r.{}.{}.{}.bin", region.x, region.y, region.z))
+    }
+}
+
+// the region a chunk is saved in, and the chunk's slot in that region
+fn region_of(position : ChunkPos) -> (RegionPos, usize) {
+    let region = RegionPos {
+        x: position.x.div_euclid(REGION_SIZE),
+        y: position.y,
+        z: position.z.div_euclid(REGION_SIZE),
+    };
+
+    let index = position.x.rem_euclid(REGION_SIZE) + position.z.rem_euclid(REGION_SIZE) * REGION_SIZE;
+
+    (region, index as usize)
+}
+
+fn read_header(file : &mut File) -> io::Result<Vec<(u32, u32)>> {
+    let mut header = vec![0; HEADER_SIZE];
+    file.read_exact(&mut header)?;
+
+    if &header[..REGION_MAGIC.len()] != REGION_MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a region file"));
+    }
+
+    Ok(header[REGION_MAGIC.len()..]
+        .chunks_exact(8)
+        .map(|entry| (read_u32(&entry[0..4]), read_u32(&entry[4..8])))
+        .collect())
+}
+
+// every chunk payload in a region file, still compressed
+fn read_region(path : &Path) -> io::Result<Vec<Option<Vec<u8>>>> {
+    let mut file = File::open(path)?;
+    let entries = read_header(&mut file)?;
+
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents)?;
+
+    entries
+        .iter()
+        .map(|&(offset, length)| {
+            if offset == 0 {
+                return Ok(None);
+            }
+
+            check_entry(offset, length, (HEADER_SIZE + contents.len()) as u64)?;
+
+            let start = offset as usize - HEADER_SIZE;
+            Ok(Some(contents[start..start + length as usize].to_vec()))
+        })
+        .collect()
+}
+
+// a chunk's entry in the header has to point past the header and end inside the file
+fn check_entry(offset : u32, length : u32, file_length : u64) -> io::Result<()> {
+    if (offset as usize) < HEADER_SIZE || offset as u64 + length as u64 > file_length {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("region file has a chunk at {} with length {} outside the file", offset, length)
+        ));
+    }
+
+    Ok(())
+}
+
+fn write_region(path : &Path, payloads : &[Option<Vec<u8>>]) -> io::Result<()> {
+    let mut header = REGION_MAGIC.to_vec();
+    let mut body = Vec::new();
+
+    for payload in payloads {
+        match payload {
+            Some(payload) => {
+                header.extend_from_slice(&((HEADER_SIZE + body.len()) as u32).to_le_bytes());
+                header.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+                body.extend_from_slice(payload);
+            }
+            None => header.extend_from_slice(&[0; 8]),
+        }
+    }
+
+    header.extend_from_slice(&body);
+    write_atomically(path, &header)
+}
+
+// header followed by two bytes per block, compressed
+fn encode_chunk(chunk : &Chunk) -> io::Result<Vec<u8>> {
+    let mut data = CHUNK_MAGIC.to_vec();
+    data.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    for size in [CHUNK_SIZE, MAX_CHUNK_HEIGHT, CHUNK_SIZE] {
+        data.extend_from_slice(&(size as u16).to_le_bytes());
+    }
+    data.extend(chunk.block_data().iter().map(|block| block.id.0));
+    data.extend(chunk.block_data().iter().map(|block| block.properties));
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&data)?;
+    encoder.finish()
+}
+
+fn decode_chunk(position : ChunkPos, data : &[u8]) -> io::Result<Chunk> {
+    let mut decompressed = Vec::new();
+    ZlibDecoder::new(data).read_to_end(&mut decompressed)?;
+
+    let record = upgrade_chunk(read_chunk_record(decompressed)?)?;
+    let (width, height, depth) = record.size;
+
+    if record.blocks.len() != width * height * depth || record.properties.len() != record.blocks.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("chunk {:?} has {} blocks and {} properties, expected {}",
+                    position, record.blocks.len(), record.properties.len(), width * height * depth)
+        ));
+    }
+
+    let blocks = resize_blocks(record.size, &record.blocks)
+        .into_iter()
+        .zip(resize_blocks(record.size, &record.properties))
+        .map(|(id, properties)| BlockState { id: BlockId(id), properties })
+        .collect();
+
+    Ok(Chunk::from_blocks(position, blocks))
+}
+
+fn read_chunk_record(data : Vec<u8>) -> io::Result<ChunkRecord> {
+    // version 1 chunks were just the block ids, which are never the bytes of CHUNK_MAGIC
+    if !data.starts_with(CHUNK_MAGIC) {
+        return Ok(ChunkRecord { version: 1, size: (0, 0, 0), blocks: data, properties: Vec::new() });
+    }
+
+    if data.len() < CHUNK_HEADER_SIZE {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "chunk header is truncated"));
+    }
+
+    let header = &data[CHUNK_MAGIC.len()..CHUNK_HEADER_SIZE];
+    let version = read_u32(&header[0..4]);
+    let size = (read_u16(&header[4..6]) as usize, read_u16(&header[6..8]) as usize, read_u16(&header[8..10]) as usize);
+    let body = &data[CHUNK_HEADER_SIZE..];
+
+    // before version 3 the body was only the block ids
+    let (blocks, properties) = if version < 3 {
+        (body, &[][..])
+    } else {
+        body.split_at(body.len().min(size.0 * size.1 * size.2))
+    };
+
+    Ok(ChunkRecord { version, size, blocks: blocks.to_vec(), properties: properties.to_vec() })
+}
+
+// fit one byte per block of a saved chunk into the current chunk dimensions, cutting off blocks that no longer fit
+// and filling new space with zeros, which is air with no properties
+fn resize_blocks(size : (usize, usize, usize), bytes : &[u8]) -> Vec<u8> {
+    let (width, height, depth) = size;
+
+    if size == (CHUNK_SIZE, MAX_CHUNK_HEIGHT, CHUNK_SIZE) {
+        return bytes.to_vec();
+    }
+
+    let mut blocks = vec![0; CHUNK_VOLUME];
+
+    for y in 0..height.min(MAX_CHUNK_HEIGHT) {
+        for z in 0..depth.min(CHUNK_SIZE) {
+            for x in 0..width.min(CHUNK_SIZE) {
+                blocks[LocalPos::new(x, y, z).index()] = bytes[(y * depth + z) * width + x];
+            }
+        }
+    }
+
+    blocks
+}
+
+fn read_u16(bytes : &[u8]) -> u16 {
+    u16::from_le_bytes([bytes[0], bytes[1]])
+}
+
+fn read_u32(bytes : &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+// write to a temporary file first so a crash or a worker reading at the same time never sees half a file
+fn write_atomically(path : &Path, data : &[u8]) -> io::Result<()> {
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, data)?;
+    fs::rename(&temporary, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // an empty directory for one test to save a world in
+    fn scratch_directory(test : &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("rustworld-storage-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn edited_chunk(position : ChunkPos) -> Chunk {
+        let mut chunk = Chunk::new(position);
+        chunk.set(LocalPos::new(0, 0, 0), BlockState { id: BlockId(3), properties: 0 });
+        chunk.set(LocalPos::new(5, MAX_CHUNK_HEIGHT - 1, CHUNK_SIZE - 1), BlockState { id: BlockId(7), properties: 2 });
+        chunk
+    }
+
+    fn error_kind<T>(result : io::Result<T>) -> Option<io::ErrorKind> {
+        result.err().map(|error| error.kind())
+    }
+
+    #[test]
+    fn saved_chunks_load_with_the_same_blocks() {
+        let directory = scratch_directory("round-trip");
+        let storage = WorldStorage::open(&directory).unwrap();
+        let chunks = [edited_chunk(ChunkPos::new(0, 0, 0)), edited_chunk(ChunkPos::new(-1, 0, -33))];
+
+        storage.save_chunks(&chunks).unwrap();
+
+        for chunk in &chunks {
+            let loaded = storage.load_chunk(chunk.position).unwrap().unwrap();
+            assert_eq!(loaded.block_data(), chunk.block_data());
+        }
+        assert!(storage.load_chunk(ChunkPos::new(1, 0, 0)).unwrap().is_none());
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn saving_again_leaves_the_region_readable() {
+        let directory = scratch_directory("save-twice");
+        let storage = WorldStorage::open(&directory).unwrap();
+        let first = edited_chunk(ChunkPos::new(2, 0, 3));
+        let mut second = edited_chunk(ChunkPos::new(4, 0, 3));
+
+        storage.save_chunks([&first]).unwrap();
+        second.set(LocalPos::new(1, 1, 1), BlockState { id: BlockId(9), properties: 0 });
+        storage.save_chunks([&second]).unwrap();
+
+        assert_eq!(storage.load_chunk(first.position).unwrap().unwrap().block_data(), first.block_data());
+        assert_eq!(storage.load_chunk(second.position).unwrap().unwrap().block_data(), second.block_data());
+        // the temporary file has been renamed over the region
+        assert!(!storage.region_path(region_of(first.position).0).with_extension("tmp").exists());
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn corrupt_regions_are_errors() {
+        let directory = scratch_directory("corrupt");
+        let storage = WorldStorage::open(&directory).unwrap();
+        let chunk = edited_chunk(ChunkPos::new(0, 0, 0));
+        let path = storage.region_path(region_of(chunk.position).0);
+
+        storage.save_chunks([&chunk]).unwrap();
+        let region = fs::read(&path).unwrap();
+
+        // cut off partway through the chunk
+        fs::write(&path, &region[..region.len() - 4]).unwrap();
+        assert!(storage.load_chunk(chunk.position).is_err());
+        assert!(storage.save_chunks([&chunk]).is_err());
+
+        // an offset inside the header
+        let mut corrupt = region.clone();
+        corrupt[REGION_MAGIC.len()..REGION_MAGIC.len() + 4].copy_from_slice(&1u32.to_le_bytes());
+        fs::write(&path, &corrupt).unwrap();
+        assert_eq!(error_kind(storage.load_chunk(chunk.position)), Some(io::ErrorKind::InvalidData));
+        assert_eq!(error_kind(storage.save_chunks([&chunk])), Some(io::ErrorKind::InvalidData));
+
+        // a length far past the end of the file
+        let mut corrupt = region.clone();
+        corrupt[REGION_MAGIC.len() + 4..REGION_MAGIC.len() + 8].copy_from_slice(&u32::MAX.to_le_bytes());
+        fs::write(&path, &corrupt).unwrap();
+        assert_eq!(error_kind(storage.load_chunk(chunk.position)), Some(io::ErrorKind::InvalidData));
+
+        // too short for a header
+        fs::write(&path, REGION_MAGIC).unwrap();
+        assert!(storage.load_chunk(chunk.position).is_err());
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -1,55 +1,349 @@
-use cgmath::Vector3;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use cgmath::{InnerSpace, Vector3};
 use rand::Rng;
 use crate::creation::chunk::Chunk;
-use crate::game_specs::{CHUNK_SIZE, NUM_CHUNKS};
+use crate::creation::block_registry::{BlockId, BlockState};
+use crate::creation::position::{BlockPos, ChunkPos};
+use crate::creation::migration::FORMAT_VERSION;
+use crate::creation::storage::{LevelData, WorldStorage};
+use crate::game_specs::{CHUNK_SIZE, MAX_CHUNK_HEIGHT, SPAWN_POSITION};
 
 pub struct World {
-    pub chunks : Vec<Chunk>,
-    pub world_seed : u32
+    chunks : HashMap<ChunkPos, Chunk>,
+    world_seed : u32,
+    spawn : Vector3<f32>,
+    // where the world is saved, None until it's saved for the first time
+    storage : Option<Arc<WorldStorage>>,
+    // edited chunks that have been unloaded but not saved yet, either because the world has nowhere to save them
+    // or because the save hasn't finished or failed
+    unloaded_edits : HashMap<ChunkPos, Chunk>,
 }
 
 impl World {
+    // create an empty world, chunks are generated as the camera moves around it (see ChunkManager)
     pub fn new() -> Self {
-        let mut chunks = Vec::new();
-        let world_seed = rand::thread_rng().gen();  // Generate a random seed for the entire world
-        let mut previous_chunk : Option<Chunk> = None;
+        World::with_seed(rand::thread_rng().gen())  // Generate a random seed for the entire world
+    }
+
+    // create an empty world whose terrain is generated from the given seed, the same seed always gives the same world
+    pub fn with_seed(world_seed : u32) -> Self {
+        World {
+            chunks: HashMap::new(),
+            world_seed,
+            spawn: Vector3::from(SPAWN_POSITION),
+            storage: None,
+            unloaded_edits: HashMap::new(),
+        }
+    }
+
+    // open a saved world, its chunks are read from disk as the camera comes near them
+    pub fn load(directory: &Path) -> io::Result<Self> {
+        let storage = WorldStorage::open(directory)?;
+        let level = storage.read_level()?;
+
+        let mut world = World::with_seed(level.seed);
+        world.spawn = Vector3::from(level.spawn);
+        world.storage = Some(Arc::new(storage));
+
+        Ok(world)
+    }
+
+    // save the level data and every edited chunk, the world keeps saving to this directory from now on
+    pub fn save(&mut self, directory: &Path) -> io::Result<()> {
+        let same_directory = self.storage.as_ref().is_some_and(|storage| storage.directory() == directory);
+
+        if !same_directory {
+            self.storage = Some(Arc::new(WorldStorage::open(directory)?));
+        }
+
+        let storage = self.storage.clone().unwrap();
+
+        storage.write_level(&LevelData {
+            version: FORMAT_VERSION,
+            seed: self.world_seed,
+            spawn: self.spawn.into(),
+        })?;
+
+        // chunks only need saving if they've been edited, the rest can be generated again from the seed
+        storage.save_chunks(
+            self.chunks.values()
+                .chain(self.unloaded_edits.values())
+                .filter(|chunk| chunk.is_edited())
+        )?;
+
+        for chunk in self.chunks.values_mut() {
+            chunk.clear_edited();
+        }
+        self.unloaded_edits.clear();
+
+        Ok(())
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.world_seed
+    }
+
+    pub fn spawn(&self) -> Vector3<f32> {
+        self.spawn
+    }
+
+    pub fn set_spawn(&mut self, spawn: Vector3<f32>) {
+        self.spawn = spawn;
+    }
+
+    pub fn storage(&self) -> Option<Arc<WorldStorage>> {
+        self.storage.clone()
+    }
+
+    pub fn chunk(&self, position: ChunkPos) -> Option<&Chunk> {
+        self.chunks.get(&position)
+    }
+
+    pub fn chunk_mut(&mut self, position: ChunkPos) -> Option<&mut Chunk> {
+        self.chunks.get_mut(&position)
+    }
+
+    pub fn chunk_positions(&self) -> impl Iterator<Item = ChunkPos> + '_ {
+        self.chunks.keys().copied()
+    }
+
+    pub fn contains_chunk(&self, position: ChunkPos) -> bool {
+        self.chunks.contains_key(&position)
+    }
+
+    // add a chunk to the world, returning any chunk it replaced
+    pub fn insert_chunk(&mut self, chunk: Chunk) -> Option<Chunk> {
+        let position = chunk.position;
+        let replaced = self.chunks.insert(position, chunk);
+        self.mark_neighbors_dirty(position);
+        replaced
+    }
+
+    // take a chunk out of the world, its edits go with it
+    pub fn remove_chunk(&mut self, position: ChunkPos) -> Option<Chunk> {
+        let removed = self.chunks.remove(&position);
+        if removed.is_some() {
+            self.mark_neighbors_dirty(position);
+        }
+
+        removed
+    }
+
+    // remove a chunk that has gone out of range. Its edits are kept in memory until they're saved, a copy to save
+    // is returned if the world has somewhere to save it
+    pub fn unload_chunk(&mut self, position: ChunkPos) -> Option<Chunk> {
+        let chunk = self.remove_chunk(position).filter(Chunk::is_edited)?;
+        let to_save = self.storage.as_ref().map(|_| chunk.clone());
+        self.unloaded_edits.insert(position, chunk);
+
+        to_save
+    }
+
+    // forget an unloaded chunk's edits now that they've been saved, unless it has been edited again since
+    pub fn unloaded_chunk_saved(&mut self, chunk: &Chunk) {
+        let unchanged = self.unloaded_edits
+            .get(&chunk.position)
+            .is_some_and(|edits| edits.block_data() == chunk.block_data());
+
+        if unchanged {
+            self.unloaded_edits.remove(&chunk.position);
+        }
+    }
+
+    // bring back an edited chunk that was unloaded before the world was saved
+    pub fn restore_unloaded_edits(&mut self, position: ChunkPos) -> bool {
+        match self.unloaded_edits.remove(&position) {
+            Some(mut chunk) => {
+                chunk.mark_dirty();
+                self.insert_chunk(chunk);
+                true
+            }
+            None => false,
+        }
+    }
 
-        // TODO chunks should eventually be loaded in a radius from player position
-        for x in 0..NUM_CHUNKS {
-            for z in 0..NUM_CHUNKS {
-                let new_chunk_position = Vector3::new(x as f32, -1.0, z as f32);
-                let new_chunk = Chunk::generate(new_chunk_position, previous_chunk.as_ref(), world_seed);
+    // the loaded chunks that share a face with the chunk at the given position
+    pub fn neighbors(&self, position: ChunkPos) -> impl Iterator<Item = &Chunk> {
+        position
+            .neighbors()
+            .into_iter()
+            .filter_map(move |neighbor_position| self.chunk(neighbor_position))
+    }
+
+    // get the type of the block at a world position, or None if its chunk isn't loaded
+    pub fn get_block(&self, position: BlockPos) -> Option<BlockState> {
+        self.chunk(position.chunk_pos()).map(|chunk| chunk.get(position.local_pos()))
+    }
 
-                chunks.push(new_chunk);
+    // change the block at a world position, returns false if its chunk isn't loaded
+    pub fn set_block(&mut self, position: BlockPos, _type: BlockState) -> bool {
+        let changed = self
+            .chunk_mut(position.chunk_pos())
+            .is_some_and(|chunk| chunk.set(position.local_pos(), _type));
 
-                // Update previous_chunk to Some(new_chunk) for the next iteration
-                previous_chunk = Some(chunks.last().unwrap().clone());
+        if !changed {
+            return false;
+        }
+
+        // a block on the edge of a chunk can expose or hide faces of the chunk next to it
+        for neighbor_position in border_chunks(position) {
+            if let Some(neighbor) = self.chunk_mut(neighbor_position) {
+                neighbor.mark_dirty();
             }
         }
 
-        World {
-            chunks,
-            world_seed
+        true
+    }
+
+    // the first solid block along a ray, up to reach blocks away, or None if there isn't one loaded
+    pub fn target_block(&self, origin: Vector3<f32>, direction: Vector3<f32>, reach: f32) -> Option<BlockPos> {
+        // small enough steps that the ray can't skip over the corner of a block
+        const STEP : f32 = 0.05;
+        let direction = direction.normalize();
+
+        (0..=(reach / STEP) as usize)
+            .map(|step| BlockPos::from_world(origin + direction * (step as f32 * STEP)))
+            .find(|&position| self.get_block(position).is_some_and(|block| block.id != BlockId::AIR))
+    }
+
+    // positions of every chunk that changed since the last call, clearing their dirty flags
+    pub fn take_dirty_chunks(&mut self) -> Vec<ChunkPos> {
+        self.chunks
+            .values_mut()
+            .filter(|chunk| chunk.is_dirty())
+            .map(|chunk| {
+                chunk.clear_dirty();
+                chunk.position
+            })
+            .collect()
+    }
+
+    // faces on the border of the chunks next to this one depend on whether it's loaded
+    fn mark_neighbors_dirty(&mut self, position: ChunkPos) {
+        for neighbor_position in position.neighbors() {
+            if let Some(neighbor) = self.chunk_mut(neighbor_position) {
+                neighbor.mark_dirty();
+            }
         }
     }
+}
+
+// chunks that share a face with the given block
+fn border_chunks(position: BlockPos) -> Vec<ChunkPos> {
+    let chunk_position = position.chunk_pos();
+    let local = position.local_pos();
+    let mut neighbors = Vec::new();
+
+    if local.x == 0 { neighbors.push(chunk_position.offset(-1, 0, 0)); }
+    if local.x == CHUNK_SIZE - 1 { neighbors.push(chunk_position.offset(1, 0, 0)); }
+    if local.y == 0 { neighbors.push(chunk_position.offset(0, -1, 0)); }
+    if local.y == MAX_CHUNK_HEIGHT - 1 { neighbors.push(chunk_position.offset(0, 1, 0)); }
+    if local.z == 0 { neighbors.push(chunk_position.offset(0, 0, -1)); }
+    if local.z == CHUNK_SIZE - 1 { neighbors.push(chunk_position.offset(0, 0, 1)); }
 
-    fn get_chunk_at(&self, position: Vector3<f32>) -> Option<&Chunk> {
-        // Find and return the chunk at the specified position
-        self.chunks.iter().find(|&chunk| chunk.position == position)
+    neighbors
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::creation::position::LocalPos;
+    use super::*;
+
+    const STONE : BlockState = BlockState { id: BlockId(3), properties: 0 };
+
+    // a world with empty chunks from x = -2 to 1
+    fn world() -> World {
+        let mut world = World::with_seed(0);
+
+        for x in -2..=1 {
+            world.insert_chunk(Chunk::new(ChunkPos::new(x, 0, 0)));
+        }
+        world.take_dirty_chunks();
+
+        world
     }
 
-    pub fn generate_chunk(&mut self, position: Vector3<f32>) {
-        if !self.chunk_exists(position) {
-            // Check if the neighboring chunks exist
-            let neighbor_chunk_x = self.get_chunk_at(position + Vector3::new(CHUNK_SIZE as f32, 0.0, 0.0));
-            let neighbor_chunk_z = self.get_chunk_at(position + Vector3::new(0.0, 0.0, CHUNK_SIZE as f32));
+    #[test]
+    fn blocks_are_set_in_the_chunk_that_owns_them() {
+        let mut world = world();
+
+        for x in [-1, -16, -17, 0, 15] {
+            let position = BlockPos::new(x, 3, 4);
+            assert_eq!(world.get_block(position), Some(BlockState::AIR));
 
-            let chunk = Chunk::generate(position, neighbor_chunk_x, self.world_seed);
-            self.chunks.push(chunk);
+            assert!(world.set_block(position, STONE));
+            assert_eq!(world.get_block(position), Some(STONE));
         }
+
+        let chunk = world.chunk(ChunkPos::new(-1, 0, 0)).unwrap();
+        assert_eq!(chunk.get(LocalPos::new(CHUNK_SIZE - 1, 3, 4)), STONE);
+        assert_eq!(chunk.get(LocalPos::new(0, 3, 4)), STONE);
+        assert_eq!(world.chunk(ChunkPos::new(-2, 0, 0)).unwrap().get(LocalPos::new(CHUNK_SIZE - 1, 3, 4)), STONE);
+
+        // nothing is loaded there
+        assert!(!world.set_block(BlockPos::new(100, 3, 4), STONE));
+        assert_eq!(world.get_block(BlockPos::new(100, 3, 4)), None);
     }
 
-    fn chunk_exists(&self, position: Vector3<f32>) -> bool {
-        self.chunks.iter().any(|chunk| chunk.position == position)
+    #[test]
+    fn edits_on_a_border_mark_the_neighbor_dirty() {
+        let mut world = world();
+        let dirty = |world : &mut World| {
+            let mut dirty : Vec<_> = world.take_dirty_chunks().into_iter().map(|position| position.x).collect();
+            dirty.sort();
+            dirty
+        };
+
+        // x = -1 is the last block of chunk -1, next to chunk 0
+        world.set_block(BlockPos::new(-1, 3, 4), STONE);
+        assert_eq!(dirty(&mut world), vec![-1, 0]);
+
+        // x = -16 is the first block of chunk -1, next to chunk -2
+        world.set_block(BlockPos::new(-16, 3, 4), STONE);
+        assert_eq!(dirty(&mut world), vec![-2, -1]);
+
+        // in the middle of the chunk only the chunk itself changes
+        world.set_block(BlockPos::new(-8, 3, 4), STONE);
+        assert_eq!(dirty(&mut world), vec![-1]);
+    }
+
+    #[test]
+    fn neighbors_are_the_loaded_chunks_next_to_a_chunk() {
+        let mut world = world();
+        let neighbors = |world : &World, x| {
+            let mut neighbors : Vec<_> = world.neighbors(ChunkPos::new(x, 0, 0)).map(|chunk| chunk.position.x).collect();
+            neighbors.sort();
+            neighbors
+        };
+
+        assert_eq!(neighbors(&world, -1), vec![-2, 0]);
+        assert_eq!(neighbors(&world, 1), vec![0]);
+        assert_eq!(neighbors(&world, 5), Vec::<i32>::new());
+
+        // removing a chunk takes it out of its neighbors' lookups and marks them for meshing again
+        assert_eq!(world.remove_chunk(ChunkPos::new(0, 0, 0)).map(|chunk| chunk.position), Some(ChunkPos::new(0, 0, 0)));
+        assert!(world.remove_chunk(ChunkPos::new(0, 0, 0)).is_none());
+        assert_eq!(neighbors(&world, -1), vec![-2]);
+
+        let mut dirty : Vec<_> = world.take_dirty_chunks().into_iter().map(|position| position.x).collect();
+        dirty.sort();
+        assert_eq!(dirty, vec![-1, 1]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn target_block_finds_the_first_solid_block() {
+        let mut world = world();
+        world.set_block(BlockPos::new(-5, 3, 4), STONE);
+        world.set_block(BlockPos::new(-9, 3, 4), STONE);
+
+        let origin = Vector3::new(0.0, 3.0, 4.0);
+        let west = Vector3::new(-1.0, 0.0, 0.0);
+
+        assert_eq!(world.target_block(origin, west, 8.0), Some(BlockPos::new(-5, 3, 4)));
+        assert_eq!(world.target_block(origin, west, 4.0), None);
+        assert_eq!(world.target_block(origin, -west, 8.0), None);
+    }
+}
@@ -1,5 +1,9 @@
 use crate::core::lib::PolygonMode;
 use crate::core::lib::PolygonMode::*;
+use crate::creation::atlas_packer::AtlasOptions;
+use crate::rendering::mesher::MeshMode;
+use crate::rendering::renderer::RenderPath;
+use crate::rendering::texture::TextureBackend;
 
 pub const TITLE : &str = "RustWorld";
 
@@ -7,8 +11,24 @@ pub const TITLE : &str = "RustWorld";
 pub const WINDOW_WIDTH : u32 = 800;
 pub const WINDOW_HEIGHT : u32 = 600;
 pub const CHUNK_SIZE : usize = 16;
-pub const NUM_CHUNKS : usize = 6;
 pub const CHUNK_RADIUS : usize = 2;
+pub const CHUNK_UNLOAD_HYSTERESIS : usize = 1;
+pub const TERRAIN_CHUNK_LAYER : i32 = -1;
+pub const CHUNK_WORKER_THREADS : usize = 4;
+pub const SPAWN_POSITION : [f32; 3] = [0.0, 10.0, 70.0];
+// how far away a block can be broken, in blocks
+pub const REACH : f32 = 6.0;
 pub const MAX_CHUNK_HEIGHT : usize = 10;
-pub const MIN_CHUNK_HEIGHT : usize = 1;
-pub const POLYGON_MODE : PolygonMode = Fill;
\ No newline at end of file
+pub const POLYGON_MODE : PolygonMode = Fill;
+pub const MESH_MODE : MeshMode = MeshMode::Naive;
+pub const RENDER_PATH : RenderPath = RenderPath::Meshed;
+// resource packs are layered over the assets in this directory, see core::assets
+pub const BUILT_IN_ASSETS : &str = "resources";
+// the block atlas is packed from the PNGs in this asset directory when the game starts
+pub const BLOCK_TEXTURE_DIRECTORY : &str = "textures/blocks";
+pub const ATLAS_OPTIONS : AtlasOptions = AtlasOptions { padding: 2, extrusion: 8 };
+// a texture array never bleeds between textures, but every texture is scaled to the size of the largest.
+// This is the default, --texture-backend picks one when the game starts
+pub const TEXTURE_BACKEND : TextureBackend = TextureBackend::Atlas;
+// how often --watch-assets checks for changed files, in seconds
+pub const ASSET_POLL_INTERVAL : f32 = 0.5;
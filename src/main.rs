@@ -3,8 +3,17 @@ mod core;
 mod creation;
 mod rendering;
 
+use crate::core::config::{GameConfig, USAGE};
 use crate::core::game::Game;
 
 fn main() {
-    Game::run(&Game::new());
+    let config = match GameConfig::from_args(std::env::args().skip(1)) {
+        Ok(config) => config,
+        Err(message) => {
+            eprintln!("{}\n{}", message, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    Game::run(&Game::new(config));
 }
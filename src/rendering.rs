@@ -1,4 +1,9 @@
 pub mod renderer;
 pub mod shader;
+pub mod preprocessor;
 pub mod texture;
-pub mod mesh;
\ No newline at end of file
+pub mod mesh;
+pub mod mesher;
+pub mod instancing;
+pub mod uniform_buffer;
+pub mod gl_object;
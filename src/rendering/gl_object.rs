@@ -0,0 +1,200 @@
+use std::cell::Cell;
+use std::marker::PhantomData;
+use gl::types::{GLenum, GLuint};
+
+// Owning handles for GL objects, which delete the object when they're dropped. GL objects belong to the context
+// that made them and can only be used on the thread it's current on, so handles can't be sent to other threads,
+// and once the context has been destroyed (see context_destroyed) dropping a handle only stops counting it.
+//
+// Live objects are counted by kind so tests and the frame stats can check nothing is left behind as chunks unload.
+
+pub trait GlKind {
+    const NAME : &'static str;
+    // index into the live counts
+    const INDEX : usize;
+
+    unsafe fn delete(id : GLuint);
+}
+
+pub struct GlObject<K : GlKind> {
+    id : GLuint,
+    // GL objects stay on the context's thread
+    kind : PhantomData<(K, *const ())>,
+}
+
+pub enum BufferKind {}
+pub enum VertexArrayKind {}
+pub enum TextureKind {}
+pub enum ShaderKind {}
+pub enum ProgramKind {}
+
+pub type Buffer = GlObject<BufferKind>;
+pub type VertexArray = GlObject<VertexArrayKind>;
+pub type GlTexture = GlObject<TextureKind>;
+pub type GlShader = GlObject<ShaderKind>;
+pub type Program = GlObject<ProgramKind>;
+
+const KINDS : usize = 5;
+
+thread_local! {
+    static CONTEXT_CURRENT : Cell<bool> = const { Cell::new(false) };
+    static LIVE_OBJECTS : [Cell<usize>; KINDS] = const { [const { Cell::new(0) }; KINDS] };
+}
+
+// call once the GL functions have been loaded for this thread's context
+pub fn context_created() {
+    CONTEXT_CURRENT.with(|current| current.set(true));
+}
+
+// call before the context is destroyed, objects dropped afterwards have already gone with it
+pub fn context_destroyed() {
+    CONTEXT_CURRENT.with(|current| current.set(false));
+}
+
+// how many objects of a kind are alive on this thread
+pub fn live_count<K : GlKind>() -> usize {
+    LIVE_OBJECTS.with(|live| live[K::INDEX].get())
+}
+
+// the live count of every kind, for printing
+pub fn live_counts() -> String {
+    [
+        (BufferKind::NAME, live_count::<BufferKind>()),
+        (VertexArrayKind::NAME, live_count::<VertexArrayKind>()),
+        (TextureKind::NAME, live_count::<TextureKind>()),
+        (ShaderKind::NAME, live_count::<ShaderKind>()),
+        (ProgramKind::NAME, live_count::<ProgramKind>()),
+    ]
+        .iter()
+        .map(|(name, count)| format!("{} {}", count, name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl<K : GlKind> GlObject<K> {
+    // take ownership of an object that has just been made
+    pub fn from_raw(id : GLuint) -> Self {
+        LIVE_OBJECTS.with(|live| live[K::INDEX].set(live[K::INDEX].get() + 1));
+
+        GlObject {
+            id,
+            kind: PhantomData,
+        }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+}
+
+impl<K : GlKind> Drop for GlObject<K> {
+    fn drop(&mut self) {
+        LIVE_OBJECTS.with(|live| live[K::INDEX].set(live[K::INDEX].get() - 1));
+
+        if CONTEXT_CURRENT.with(Cell::get) {
+            unsafe { K::delete(self.id) }
+        }
+    }
+}
+
+impl Buffer {
+    pub fn new() -> Self {
+        let mut id = 0;
+        unsafe { gl::GenBuffers(1, &mut id) }
+        Buffer::from_raw(id)
+    }
+}
+
+impl VertexArray {
+    pub fn new() -> Self {
+        let mut id = 0;
+        unsafe { gl::GenVertexArrays(1, &mut id) }
+        VertexArray::from_raw(id)
+    }
+}
+
+impl GlTexture {
+    pub fn new() -> Self {
+        let mut id = 0;
+        unsafe { gl::GenTextures(1, &mut id) }
+        GlTexture::from_raw(id)
+    }
+}
+
+impl GlShader {
+    // shader_type is VERTEX_SHADER or FRAGMENT_SHADER
+    pub fn new(shader_type : GLenum) -> Self {
+        GlShader::from_raw(unsafe { gl::CreateShader(shader_type) })
+    }
+}
+
+impl Program {
+    pub fn new() -> Self {
+        Program::from_raw(unsafe { gl::CreateProgram() })
+    }
+}
+
+impl GlKind for BufferKind {
+    const NAME : &'static str = "buffers";
+    const INDEX : usize = 0;
+
+    unsafe fn delete(id : GLuint) {
+        gl::DeleteBuffers(1, &id);
+    }
+}
+
+impl GlKind for VertexArrayKind {
+    const NAME : &'static str = "vertex arrays";
+    const INDEX : usize = 1;
+
+    unsafe fn delete(id : GLuint) {
+        gl::DeleteVertexArrays(1, &id);
+    }
+}
+
+impl GlKind for TextureKind {
+    const NAME : &'static str = "textures";
+    const INDEX : usize = 2;
+
+    unsafe fn delete(id : GLuint) {
+        gl::DeleteTextures(1, &id);
+    }
+}
+
+impl GlKind for ShaderKind {
+    const NAME : &'static str = "shaders";
+    const INDEX : usize = 3;
+
+    unsafe fn delete(id : GLuint) {
+        gl::DeleteShader(id);
+    }
+}
+
+impl GlKind for ProgramKind {
+    const NAME : &'static str = "programs";
+    const INDEX : usize = 4;
+
+    unsafe fn delete(id : GLuint) {
+        gl::DeleteProgram(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // there's no context in tests, so only the counting happens
+    #[test]
+    fn objects_are_counted_until_dropped() {
+        let meshes : Vec<_> = (1..=3).map(|id| (VertexArray::from_raw(id), Buffer::from_raw(id), Buffer::from_raw(id + 10))).collect();
+        assert_eq!((live_count::<VertexArrayKind>(), live_count::<BufferKind>()), (3, 6));
+
+        drop(meshes);
+        assert_eq!((live_count::<VertexArrayKind>(), live_count::<BufferKind>()), (0, 0));
+
+        let program = Program::from_raw(1);
+        assert_eq!(live_counts(), "0 buffers, 0 vertex arrays, 0 textures, 0 shaders, 1 programs");
+        drop(program);
+        assert_eq!(live_count::<ProgramKind>(), 0);
+    }
+}
@@ -0,0 +1,102 @@
+use std::ffi::c_void;
+use std::mem::{offset_of, size_of, size_of_val};
+use cgmath::{Vector3, Vector4};
+use gl::types::{GLsizei, GLuint};
+use crate::rendering::gl_object::{Buffer, VertexArray};
+use crate::rendering::mesh::{float_attribute, FACE_LAYERS_LOCATION, FACE_TILES_LOCATION, OFFSET_LOCATION, POSITION_LOCATION, TEXTURE_LOCATION};
+use crate::rendering::mesher::Face;
+
+// Draws many copies of the shared cube with one call. The cube's vertices come from Cube::VERTICES and each copy
+// is moved into place by its instance, so nothing but the instance list changes when chunks load or unload.
+// Rotated blocks get the right texture on each face, but the textures themselves aren't turned like in chunk meshes.
+
+// the faces of Cube::VERTICES in the order they're listed, six vertices each
+pub const CUBE_FACES : [Face; 6] = [Face::Back, Face::Front, Face::Left, Face::Right, Face::Bottom, Face::Top];
+
+// one cube to draw
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Instance {
+    // world position of the block's centre
+    pub position : Vector3<f32>,
+    // atlas rectangles of the block's faces in CUBE_FACES order, see mesher::face_texture
+    pub face_tiles : [Vector4<f32>; 6],
+    // texture array layers of the block's faces in the same order
+    pub face_layers : [f32; 6],
+}
+
+// the instances of one block type, drawn together
+pub struct InstanceBatch {
+    vao : VertexArray,
+    instance_vbo : Buffer,
+    count : GLsizei,
+}
+
+impl InstanceBatch {
+    // cube_vbo holds Cube::VERTICES, it's shared between every batch
+    pub fn new(cube_vbo : &Buffer) -> Self {
+        let batch = InstanceBatch {
+            vao: VertexArray::new(),
+            instance_vbo: Buffer::new(),
+            count: 0,
+        };
+
+        unsafe { batch.setup(cube_vbo) }
+
+        batch
+    }
+
+    // replace the cubes this batch draws
+    pub fn upload(&mut self, instances : &[Instance]) {
+        self.count = instances.len() as GLsizei;
+
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.instance_vbo.id());
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                size_of_val(instances) as isize,
+                instances.as_ptr() as *const c_void,
+                gl::DYNAMIC_DRAW
+            );
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub unsafe fn draw(&self) {
+        gl::BindVertexArray(self.vao.id());
+        gl::DrawArraysInstanced(gl::TRIANGLES, 0, 36, self.count);
+    }
+
+    unsafe fn setup(&self, cube_vbo : &Buffer) {
+        gl::BindVertexArray(self.vao.id());
+
+        // per vertex: the cube's position and texture coordinates
+        gl::BindBuffer(gl::ARRAY_BUFFER, cube_vbo.id());
+        let cube_stride = (5 * size_of::<f32>()) as GLsizei;
+        float_attribute(POSITION_LOCATION, 3, cube_stride, 0);
+        float_attribute(TEXTURE_LOCATION, 2, cube_stride, 3 * size_of::<f32>());
+
+        // per instance: where the cube goes and what it's made of
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.instance_vbo.id());
+        let instance_stride = size_of::<Instance>() as GLsizei;
+        float_attribute(OFFSET_LOCATION, 3, instance_stride, offset_of!(Instance, position));
+        gl::VertexAttribDivisor(OFFSET_LOCATION, 1);
+
+        for face in 0..CUBE_FACES.len() {
+            let location = FACE_TILES_LOCATION + face as GLuint;
+            float_attribute(location, 4, instance_stride, offset_of!(Instance, face_tiles) + face * size_of::<Vector4<f32>>());
+            gl::VertexAttribDivisor(location, 1);
+        }
+
+        for half in 0..2 {
+            let location = FACE_LAYERS_LOCATION + half as GLuint;
+            float_attribute(location, 3, instance_stride, offset_of!(Instance, face_layers) + half * 3 * size_of::<f32>());
+            gl::VertexAttribDivisor(location, 1);
+        }
+
+        gl::BindVertexArray(0);
+    }
+}
@@ -1,16 +1,32 @@
-use std::ffi::{c_void, CString};
-use std::{mem, ptr};
-use std::mem::{size_of};
-use cgmath::{Vector2, Vector3, Zero};
-use gl::types::{GLfloat, GLsizei, GLuint};
-use crate::creation::world::World;
-use crate::rendering::shader::Shader;
-use crate::rendering::texture::Texture;
-
+use std::ffi::c_void;
+use std::mem::{offset_of, size_of, size_of_val};
+use cgmath::{Vector2, Vector3, Vector4, Zero};
+use gl::types::{GLsizei, GLuint};
+use crate::rendering::gl_object::{Buffer, VertexArray};
+use crate::rendering::mesher::MeshData;
+
+// attribute locations, these match the layout qualifiers in shader.vert
+pub const POSITION_LOCATION : GLuint = 0;
+pub const TEXTURE_LOCATION : GLuint = 1;
+pub const NORMAL_LOCATION : GLuint = 2;
+pub const TILE_LOCATION : GLuint = 3;
+pub const OFFSET_LOCATION : GLuint = 4;
+// an array of six tiles, one location each
+pub const FACE_TILES_LOCATION : GLuint = 5;
+pub const LAYER_LOCATION : GLuint = 11;
+// six layers as two vec3s
+pub const FACE_LAYERS_LOCATION : GLuint = 12;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Vertex {
     pub position : Vector3<f32>,
     pub normal : Vector3<f32>,
-    pub texture_coords : Vector2<f32>
+    pub texture_coords : Vector2<f32>,
+    // the face's rectangle in the atlas as x, y, width and height, all from 0 to 1
+    pub tile : Vector4<f32>,
+    // the face's layer when block textures are a texture array
+    pub layer : f32,
 }
 
 impl Default for Vertex {
@@ -18,90 +34,75 @@ impl Default for Vertex {
         Vertex {
             position : Vector3::zero(),
             normal : Vector3::zero(),
-            texture_coords : Vector2::zero()
+            texture_coords : Vector2::zero(),
+            tile : Vector4::zero(),
+            layer : 0.0,
         }
     }
 }
 
+// a chunk's mesh once it has been uploaded to the GPU, its buffers are freed when it's dropped
 pub struct Mesh {
-    pub vertices : Vec<Vertex>,
-    pub indices : Vec<u32>,
-    pub texture : Texture,
-
-    // TODO probably keep this outta here
-    pub VAO : u32,
-
-    // for rendering
-    pub(crate) VBO : u32,
-    pub(crate) EBO : u32
+    vao : VertexArray,
+    vbo : Buffer,
+    ebo : Buffer,
+    index_count : GLsizei,
 }
 
 impl Mesh {
-    pub fn new(vertices : Vec<Vertex>, indices : Vec<u32>, texture : Texture, shader : Shader) -> Mesh {
-        let mut mesh = Mesh {
-            vertices, indices, texture,
-            VAO: 0, VBO: 0, EBO: 0
+    // upload a mesh built by the mesher, the data can be dropped afterwards
+    pub fn new(data : &MeshData) -> Mesh {
+        // create buffers/arrays
+        let mesh = Mesh {
+            vao: VertexArray::new(),
+            vbo: Buffer::new(),
+            ebo: Buffer::new(),
+            index_count: data.indices.len() as GLsizei,
         };
 
         // set mesh buffers and attrib pointers
-        unsafe { mesh.setup_mesh(shader) }
+        unsafe { mesh.setup_mesh(data) }
 
         mesh
     }
 
-    unsafe fn setup_mesh(&mut self, shader : Shader) {
-        // create buffers/arrays
-        gl::GenVertexArrays(1, &mut self.VAO);
-        gl::GenBuffers(1, &mut self.VBO);
-        gl::GenBuffers(1, &mut self.EBO);
+    pub unsafe fn draw(&self) {
+        gl::BindVertexArray(self.vao.id());
+        gl::DrawElements(gl::TRIANGLES, self.index_count, gl::UNSIGNED_INT, std::ptr::null());
+    }
 
-        gl::BindVertexArray(self.VAO);
+    unsafe fn setup_mesh(&self, data : &MeshData) {
+        gl::BindVertexArray(self.vao.id());
         // load data into vertex buffers
-        gl::BindBuffer(gl::ARRAY_BUFFER, self.VBO);
-
-        let size = (self.vertices.len() * size_of::<Vertex>()) as isize;
-        let data = &self.vertices[0] as *const Vertex as *const c_void;
-        gl::BufferData(gl::ARRAY_BUFFER, size, data, gl::STATIC_DRAW);
-
-        gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.EBO);
-        let size = (self.indices.len() * size_of::<u32>()) as isize;
-        let data = &self.indices[0] as *const u32 as *const c_void;
-        gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, size, data, gl::STATIC_DRAW);
-
-        let pos_attr_location = gl::GetAttribLocation(
-            shader.id,
-            CString::new("position").unwrap().as_ptr()
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo.id());
+        gl::BufferData(
+            gl::ARRAY_BUFFER,
+            size_of_val(data.vertices.as_slice()) as isize,
+            data.vertices.as_ptr() as *const c_void,
+            gl::STATIC_DRAW
         );
 
-        let texture_attr_location = gl::GetAttribLocation(
-            shader.id,
-            CString::new("texture").unwrap().as_ptr()
+        gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.ebo.id());
+        gl::BufferData(
+            gl::ELEMENT_ARRAY_BUFFER,
+            size_of_val(data.indices.as_slice()) as isize,
+            data.indices.as_ptr() as *const c_void,
+            gl::STATIC_DRAW
         );
 
-        let stride = (5 * mem::size_of::<GLfloat>()) as GLsizei;
+        let stride = size_of::<Vertex>() as GLsizei;
 
-        // position attribute
-        gl::VertexAttribPointer(
-            pos_attr_location as GLuint,
-            3,
-            gl::FLOAT,
-            gl::FALSE,
-            stride,
-            std::ptr::null(),
-        );
-        gl::EnableVertexAttribArray(pos_attr_location as GLuint);
-
-        // texture attribute
-        gl::VertexAttribPointer(
-            texture_attr_location as GLuint,
-            2,
-            gl::FLOAT,
-            gl::FALSE,
-            stride,
-            (3 * mem::size_of::<GLfloat>()) as *const std::ffi::c_void,
-        );
-        gl::EnableVertexAttribArray(texture_attr_location as GLuint);
+        float_attribute(POSITION_LOCATION, 3, stride, offset_of!(Vertex, position));
+        float_attribute(TEXTURE_LOCATION, 2, stride, offset_of!(Vertex, texture_coords));
+        float_attribute(NORMAL_LOCATION, 3, stride, offset_of!(Vertex, normal));
+        float_attribute(TILE_LOCATION, 4, stride, offset_of!(Vertex, tile));
+        float_attribute(LAYER_LOCATION, 1, stride, offset_of!(Vertex, layer));
 
         gl::BindVertexArray(0);
     }
 }
+
+pub unsafe fn float_attribute(location : GLuint, size : i32, stride : GLsizei, offset : usize) {
+    gl::VertexAttribPointer(location, size, gl::FLOAT, gl::FALSE, stride, offset as *const c_void);
+    gl::EnableVertexAttribArray(location);
+}
@@ -0,0 +1,904 @@
+use cgmath::{InnerSpace, Vector2, Vector3, Vector4, Zero};
+use crate::creation::block_config::BlockTypeConfig;
+use crate::creation::block_model::{side_name, ElementShape, ModelElement};
+use crate::creation::block_registry::{BlockDefinition, BlockRegistry, BlockState, Rotation};
+use crate::creation::chunk::Chunk;
+use crate::creation::position::{BlockPos, LocalPos};
+use crate::game_specs::{CHUNK_SIZE, MAX_CHUNK_HEIGHT};
+use crate::rendering::mesh::Vertex;
+
+// Turns a chunk's blocks into triangles on the CPU. Only faces that aren't touching an opaque block are kept, so
+// the inside of the terrain is never sent to the GPU. Nothing here needs a GL context; rendering::mesh uploads
+// the result.
+//
+// Texture coordinates count in tiles rather than atlas pixels, a quad covering 3 x 2 blocks goes from 0 to 3 and
+// 0 to 2, and the fragment shader wraps them back into the face's tile so it repeats once per block. Each vertex
+// carries its face's tile, its rectangle in the block atlas scaled to the size of the atlas.
+//
+// Blocks with a rotation (see BlockDefinition::rotation) are drawn as the unrotated block turned into place: each
+// face shows the texture of the face that was turned to point its way, with the texture turned along with it.
+//
+// Blocks whose model isn't a cube (see creation::block_model) get a quad for each face of each element instead,
+// their texture coordinates stay between 0 and 1. Those are never merged by greedy meshing, and a face is only
+// hidden by a neighbor whose model completely covers the side touching it.
+
+// the vertices and triangles of one chunk, positions are relative to the chunk's origin
+#[derive(Default)]
+pub struct MeshData {
+    pub vertices : Vec<Vertex>,
+    pub indices : Vec<u32>,
+}
+
+impl MeshData {
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+}
+
+// how exposed faces are turned into quads
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MeshMode {
+    // one quad per exposed face
+    Naive,
+    // neighboring faces of the same block and state that point the same way are merged into larger quads
+    Greedy,
+}
+
+impl MeshMode {
+    // the other mode
+    pub fn toggled(self) -> MeshMode {
+        match self {
+            MeshMode::Naive => MeshMode::Greedy,
+            MeshMode::Greedy => MeshMode::Naive,
+        }
+    }
+}
+
+// the six sides of a block
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Face {
+    Right,
+    Left,
+    Top,
+    Bottom,
+    Front,
+    Back,
+}
+
+impl Face {
+    pub const ALL : [Face; 6] = [Face::Right, Face::Left, Face::Top, Face::Bottom, Face::Front, Face::Back];
+
+    // direction the face points in
+    pub fn normal(self) -> [i32; 3] {
+        match self {
+            Face::Right => [1, 0, 0],
+            Face::Left => [-1, 0, 0],
+            Face::Top => [0, 1, 0],
+            Face::Bottom => [0, -1, 0],
+            Face::Front => [0, 0, 1],
+            Face::Back => [0, 0, -1],
+        }
+    }
+
+    // the face pointing in a direction
+    pub fn from_normal(normal : [i32; 3]) -> Face {
+        Face::ALL.into_iter().find(|face| face.normal() == normal).unwrap()
+    }
+
+    // what the face is called in blocks.json and models.json
+    pub fn name(self) -> &'static str {
+        side_name(self.normal())
+    }
+
+    pub fn opposite(self) -> Face {
+        Face::from_normal(self.normal().map(|component| -component))
+    }
+
+    // which of a sprite's textures in the block atlas the face uses
+    pub fn texture_name(self) -> &'static str {
+        match self {
+            Face::Top => "top",
+            Face::Bottom => "bottom",
+            _ => "side",
+        }
+    }
+
+    // directions of the texture's u and v axes across the face, as seen from outside the block
+    // u x v is the normal so quads built from them wind counter-clockwise
+    fn axes(self) -> ([i32; 3], [i32; 3]) {
+        match self {
+            Face::Right => ([0, 0, -1], [0, 1, 0]),
+            Face::Left => ([0, 0, 1], [0, 1, 0]),
+            Face::Top => ([1, 0, 0], [0, 0, -1]),
+            Face::Bottom => ([1, 0, 0], [0, 0, 1]),
+            Face::Front => ([1, 0, 0], [0, 1, 0]),
+            Face::Back => ([-1, 0, 0], [0, 1, 0]),
+        }
+    }
+}
+
+// build the mesh of a chunk, block_at is asked for blocks outside the chunk so faces on its border can be culled too
+pub fn mesh_chunk<F : Fn(BlockPos) -> BlockState>(chunk : &Chunk,
+                                              mode : MeshMode,
+                                              blocks : &BlockRegistry,
+                                              textures : &BlockTypeConfig,
+                                              block_at : F) -> MeshData {
+    let mut mesh = match mode {
+        MeshMode::Naive => naive_mesh(chunk, blocks, textures, &block_at),
+        MeshMode::Greedy => greedy_mesh(chunk, blocks, textures, &block_at),
+    };
+
+    add_models(&mut mesh, chunk, blocks, textures, &block_at);
+    mesh
+}
+
+// build a mesh of only the blocks that aren't cubes, to draw alongside instanced cubes
+pub fn mesh_models<F : Fn(BlockPos) -> BlockState>(chunk : &Chunk,
+                                                   blocks : &BlockRegistry,
+                                                   textures : &BlockTypeConfig,
+                                                   block_at : F) -> MeshData {
+    let mut mesh = MeshData::default();
+    add_models(&mut mesh, chunk, blocks, textures, &block_at);
+    mesh
+}
+
+fn naive_mesh<F : Fn(BlockPos) -> BlockState>(chunk : &Chunk,
+                                           blocks : &BlockRegistry,
+                                           textures : &BlockTypeConfig,
+                                           block_at : &F) -> MeshData {
+    let mut mesh = MeshData::default();
+
+    for (local, state, block) in visible_cubes(chunk, blocks) {
+        for face in Face::ALL {
+            if is_exposed(chunk, local, face, blocks, block_at) {
+                let texture = face_texture(textures, block, state.properties, face);
+                add_quad(&mut mesh, face_corner(local, face), face, (1, 1), &texture);
+            }
+        }
+    }
+
+    mesh
+}
+
+// sweep through the chunk one layer at a time for each face direction, growing each exposed face as far as it will
+// go along u and then as far as the whole row will go along v
+fn greedy_mesh<F : Fn(BlockPos) -> BlockState>(chunk : &Chunk,
+                                            blocks : &BlockRegistry,
+                                            textures : &BlockTypeConfig,
+                                            block_at : &F) -> MeshData {
+    let mut mesh = MeshData::default();
+    let chunk_size = [CHUNK_SIZE, MAX_CHUNK_HEIGHT, CHUNK_SIZE];
+
+    for face in Face::ALL {
+        let (u, v) = face.axes();
+        let (layer_axis, u_axis, v_axis) = (axis(face.normal()), axis(u), axis(v));
+        let (width, height) = (chunk_size[u_axis], chunk_size[v_axis]);
+
+        // the local position of the block at (a, b) in a layer, a along the u axis and b along the v axis
+        let block = |layer : usize, a : usize, b : usize| {
+            let mut coords = [0; 3];
+            coords[layer_axis] = layer;
+            coords[u_axis] = a;
+            coords[v_axis] = b;
+            LocalPos::new(coords[0], coords[1], coords[2])
+        };
+
+        for layer in 0..chunk_size[layer_axis] {
+            // each block in the layer whose face is exposed and hasn't been merged into a quad yet
+            let mut unmerged : Vec<Option<BlockState>> = (0..width * height)
+                .map(|index| {
+                    let local = block(layer, index % width, index / width);
+                    let state = chunk.get(local);
+
+                    let cube = blocks.is_visible(state.id) && blocks.model(state.id).is_cube();
+
+                    if cube && is_exposed(chunk, local, face, blocks, block_at) { Some(state) } else { None }
+                })
+                .collect();
+
+            for b in 0..height {
+                let mut a = 0;
+
+                while a < width {
+                    let state = match unmerged[b * width + a] {
+                        Some(state) => state,
+                        None => {
+                            a += 1;
+                            continue;
+                        }
+                    };
+
+                    let matches = |a : usize, b : usize| unmerged[b * width + a] == Some(state);
+
+                    let mut quad_width = 1;
+                    while a + quad_width < width && matches(a + quad_width, b) {
+                        quad_width += 1;
+                    }
+
+                    let mut quad_height = 1;
+                    while b + quad_height < height && (a..a + quad_width).all(|a| matches(a, b + quad_height)) {
+                        quad_height += 1;
+                    }
+
+                    for row in b..b + quad_height {
+                        unmerged[row * width + a..row * width + a + quad_width].fill(None);
+                    }
+
+                    // the quad starts from whichever block is furthest back along u and v, which are negative on some faces
+                    let start_a = if u[u_axis] > 0 { a } else { a + quad_width - 1 };
+                    let start_b = if v[v_axis] > 0 { b } else { b + quad_height - 1 };
+                    let corner = face_corner(block(layer, start_a, start_b), face);
+
+                    let texture = face_texture(textures, blocks.get(state.id).unwrap(), state.properties, face);
+                    add_quad(&mut mesh, corner, face, (quad_width, quad_height), &texture);
+
+                    a += quad_width;
+                }
+            }
+        }
+    }
+
+    mesh
+}
+
+// every cube with at least one exposed face, for drawing whole cubes instead of a mesh
+pub fn visible_blocks<'a, F : Fn(BlockPos) -> BlockState>(chunk : &'a Chunk,
+                                                      blocks : &'a BlockRegistry,
+                                                      block_at : F) -> Vec<(LocalPos, BlockState, &'a BlockDefinition)> {
+    visible_cubes(chunk, blocks)
+        .filter(|(local, _, _)| Face::ALL.iter().any(|face| is_exposed(chunk, *local, *face, blocks, &block_at)))
+        .collect()
+}
+
+// every block in the chunk that gets drawn, along with its definition
+fn visible<'a>(chunk : &'a Chunk,
+               blocks : &'a BlockRegistry) -> impl Iterator<Item = (LocalPos, BlockState, &'a BlockDefinition)> + 'a {
+    chunk
+        .blocks()
+        .filter(|(_, state)| blocks.is_visible(state.id))
+        .map(|(local, state)| (local, state, blocks.get(state.id).unwrap()))
+}
+
+fn visible_cubes<'a>(chunk : &'a Chunk,
+                     blocks : &'a BlockRegistry) -> impl Iterator<Item = (LocalPos, BlockState, &'a BlockDefinition)> + 'a {
+    visible(chunk, blocks).filter(|(_, state, _)| blocks.model(state.id).is_cube())
+}
+
+// add the elements of every visible block that isn't a cube, turned by the block's rotation
+fn add_models<F : Fn(BlockPos) -> BlockState>(mesh : &mut MeshData,
+                                              chunk : &Chunk,
+                                              blocks : &BlockRegistry,
+                                              textures : &BlockTypeConfig,
+                                              block_at : &F) {
+    for (local, state, block) in visible(chunk, blocks) {
+        let model = blocks.model(state.id);
+
+        if model.is_cube() {
+            continue;
+        }
+
+        let placed = PlacedBlock {
+            centre: Vector3::new(local.x as f32, local.y as f32, local.z as f32),
+            block,
+            properties: state.properties,
+            rotation: block.rotation(state.properties),
+        };
+
+        for element in &model.elements {
+            match element.shape {
+                ElementShape::Box => add_box(mesh, &placed, element, textures, |face| {
+                    is_exposed(chunk, local, face, blocks, block_at)
+                }),
+                ElementShape::Cross => add_cross(mesh, &placed, element, textures),
+            }
+        }
+    }
+}
+
+// a block that's being built from its model
+struct PlacedBlock<'a> {
+    centre : Vector3<f32>,
+    block : &'a BlockDefinition,
+    properties : u8,
+    rotation : Rotation,
+}
+
+impl PlacedBlock<'_> {
+    // a point of the model, in sixteenths, moved to where it is in the chunk
+    fn position(&self, point : Vector3<f32>) -> Vector3<f32> {
+        self.centre + rotate(self.rotation, point / 16.0 - Vector3::new(0.5, 0.5, 0.5))
+    }
+
+    // where one of the block's textures is
+    fn tile(&self, textures : &BlockTypeConfig, texture : &str) -> Tile {
+        let atlas_face = match texture {
+            "top" | "bottom" => texture,
+            _ => "side",
+        };
+        Tile::new(textures, self.block.texture(self.properties, texture), atlas_face)
+    }
+}
+
+// add the faces of a box element, faces lying on the side of the block are left out if a neighbor hides them
+fn add_box<E : Fn(Face) -> bool>(mesh : &mut MeshData,
+                                 placed : &PlacedBlock,
+                                 element : &ModelElement,
+                                 textures : &BlockTypeConfig,
+                                 exposed : E) {
+    for model_face in Face::ALL {
+        let element_face = match element.face(model_face.name()) {
+            Some(element_face) => element_face,
+            None => continue,
+        };
+
+        let normal = model_face.normal();
+        let (u, v) = model_face.axes();
+        let (normal_axis, u_axis, v_axis) = (axis(normal), axis(u), axis(v));
+        let face = Face::from_normal(placed.rotation.apply(normal));
+
+        let on_side = if normal[normal_axis] > 0 { element.to[normal_axis] == 16.0 } else { element.from[normal_axis] == 0.0 };
+        if on_side && !exposed(face) {
+            continue;
+        }
+
+        // the corner quads start from, on the face's side of the box and furthest back along u and v
+        let corner = |from : [f32; 3], to : [f32; 3]| {
+            let mut corner = [0.0; 3];
+            corner[normal_axis] = if normal[normal_axis] > 0 { to[normal_axis] } else { from[normal_axis] };
+            corner[u_axis] = if u[u_axis] > 0 { from[u_axis] } else { to[u_axis] };
+            corner[v_axis] = if v[v_axis] > 0 { from[v_axis] } else { to[v_axis] };
+            corner
+        };
+        let start = corner(element.from, element.to);
+        let width = element.to[u_axis] - element.from[u_axis];
+        let height = element.to[v_axis] - element.from[v_axis];
+
+        // by default the face shows the part of the texture a full block would have in the same place
+        let uv = element_face.uv.unwrap_or_else(|| {
+            let full_start = corner([0.0; 3], [16.0; 3]);
+            let a = (start[u_axis] - full_start[u_axis]).abs();
+            let b = (start[v_axis] - full_start[v_axis]).abs();
+            [a, 16.0 - b - height, a + width, 16.0 - b]
+        });
+
+        let start = Vector3::from(start);
+        let (u, v) = (to_vector(u) * width, to_vector(v) * height);
+        let texture = element_face.texture.as_deref().unwrap_or(model_face.name());
+
+        push_quad(
+            mesh,
+            [start, start + u, start + u + v, start + v].map(|corner| placed.position(corner)),
+            to_vector(face.normal()),
+            [[uv[0], uv[3]], [uv[2], uv[3]], [uv[2], uv[1]], [uv[0], uv[1]]].map(|[s, t]| Vector2::new(s, t) / 16.0),
+            placed.tile(textures, texture)
+        );
+    }
+}
+
+// add two quads crossing diagonally through a cross element, each drawn from both sides and never hidden
+fn add_cross(mesh : &mut MeshData, placed : &PlacedBlock, element : &ModelElement, textures : &BlockTypeConfig) {
+    let [from_x, from_y, from_z] = element.from;
+    let [to_x, to_y, to_z] = element.to;
+    let up = Vector3::new(0.0, to_y - from_y, 0.0);
+    let tile = placed.tile(textures, "cross");
+    let texture_coords = [[0.0, 16.0], [16.0, 16.0], [16.0, 16.0 - up.y], [0.0, 16.0 - up.y]]
+        .map(|[s, t]| Vector2::new(s, t) / 16.0);
+
+    let diagonals = [
+        (Vector3::new(from_x, from_y, from_z), Vector3::new(to_x, from_y, to_z)),
+        (Vector3::new(to_x, from_y, to_z), Vector3::new(from_x, from_y, from_z)),
+        (Vector3::new(from_x, from_y, to_z), Vector3::new(to_x, from_y, from_z)),
+        (Vector3::new(to_x, from_y, from_z), Vector3::new(from_x, from_y, to_z)),
+    ];
+
+    for (start, end) in diagonals {
+        let normal = rotate(placed.rotation, (end - start).cross(up).normalize());
+
+        push_quad(
+            mesh,
+            [start, end, end + up, start + up].map(|corner| placed.position(corner)),
+            normal,
+            texture_coords,
+            tile
+        );
+    }
+}
+
+// true if the face isn't hidden by an opaque block covering the whole side touching it
+fn is_exposed<F : Fn(BlockPos) -> BlockState>(chunk : &Chunk,
+                                           local : LocalPos,
+                                           face : Face,
+                                           blocks : &BlockRegistry,
+                                           block_at : &F) -> bool {
+    !covers(blocks, neighbor(chunk, local, face, block_at), face.opposite())
+}
+
+// true if the block is opaque and completely covers its side facing the given way
+fn covers(blocks : &BlockRegistry, state : BlockState, side : Face) -> bool {
+    if !blocks.is_opaque(state.id) {
+        return false;
+    }
+
+    let model = blocks.model(state.id);
+    model.is_cube() || model.is_full(blocks.get(state.id).unwrap().rotation(state.properties).undo(side.normal()))
+}
+
+// the block on the other side of a face, which may belong to another chunk
+fn neighbor<F : Fn(BlockPos) -> BlockState>(chunk : &Chunk, local : LocalPos, face : Face, block_at : &F) -> BlockState {
+    let [dx, dy, dz] = face.normal();
+
+    match local.offset(dx, dy, dz) {
+        Some(neighbor) => chunk.get(neighbor),
+        None => block_at(chunk.block_pos(local).offset(dx, dy, dz)),
+    }
+}
+
+// where a texture is, for both of the renderer's texture backends
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Tile {
+    // the rectangle in the atlas as x, y, width and height, all from 0 to 1
+    pub rect : Vector4<f32>,
+    // the layer in the texture array
+    pub layer : f32,
+}
+
+impl Tile {
+    fn new(textures : &BlockTypeConfig, sprite : &str, face : &str) -> Tile {
+        let (x, y, w, h) = textures.get_uv_rect(sprite, face);
+
+        Tile {
+            rect: Vector4::new(x, y, w, h),
+            layer: textures.get_layer(sprite, face) as f32,
+        }
+    }
+}
+
+// how one face of a block looks
+pub struct FaceTexture {
+    pub tile : Tile,
+    // directions the texture's u and v axes run in across the face, turned with the block
+    u : [i32; 3],
+    v : [i32; 3],
+}
+
+// the texture of the face pointing in a direction, given the block's properties
+pub fn face_texture(textures : &BlockTypeConfig, block : &BlockDefinition, properties : u8, face : Face) -> FaceTexture {
+    let rotation = block.rotation(properties);
+    // the face of the unrotated block that has been turned to point this way
+    let model_face = Face::from_normal(rotation.undo(face.normal()));
+
+    let sprite = block.texture(properties, model_face.name());
+    let (u, v) = model_face.axes();
+
+    FaceTexture {
+        tile: Tile::new(textures, sprite, model_face.texture_name()),
+        u: rotation.apply(u),
+        v: rotation.apply(v),
+    }
+}
+
+// the corner of a block's face that quads start from, blocks are unit cubes centred on their position
+fn face_corner(local : LocalPos, face : Face) -> Vector3<f32> {
+    let (u, v) = face.axes();
+    let centre = Vector3::new(local.x as f32, local.y as f32, local.z as f32);
+
+    centre + (to_vector(face.normal()) - to_vector(u) - to_vector(v)) * 0.5
+}
+
+// add the two triangles of a quad covering size.0 blocks along the face's u axis and size.1 along v
+fn add_quad(mesh : &mut MeshData, corner : Vector3<f32>, face : Face, size : (usize, usize), texture : &FaceTexture) {
+    let normal = to_vector(face.normal());
+    let (u, v) = face.axes();
+    let (u, v) = (to_vector(u) * size.0 as f32, to_vector(v) * size.1 as f32);
+    let (texture_u, texture_v) = (to_vector(texture.u), to_vector(texture.v));
+
+    let offsets = [Vector3::zero(), u, u + v, v];
+    // how far each vertex is along the texture's axes, v is flipped because the atlas is stored top row first
+    let texture_coords = offsets.map(|offset| Vector2::new(offset.dot(texture_u), -offset.dot(texture_v)));
+    // shifted by whole tiles so they start from 0, which doesn't change where the texture repeats
+    let start = texture_coords.iter().fold(Vector2::new(f32::MAX, f32::MAX), |start, coords| {
+        Vector2::new(start.x.min(coords.x), start.y.min(coords.y))
+    });
+
+    push_quad(mesh, offsets.map(|offset| corner + offset), normal, texture_coords.map(|coords| coords - start), texture.tile);
+}
+
+// add the two triangles of a quad whose corners wind counter-clockwise seen from the side its normal points to
+fn push_quad(mesh : &mut MeshData,
+             corners : [Vector3<f32>; 4],
+             normal : Vector3<f32>,
+             texture_coords : [Vector2<f32>; 4],
+             tile : Tile) {
+    let first = mesh.vertices.len() as u32;
+
+    for (position, texture_coords) in corners.into_iter().zip(texture_coords) {
+        mesh.vertices.push(Vertex {
+            position,
+            normal,
+            texture_coords,
+            tile: tile.rect,
+            layer: tile.layer,
+        });
+    }
+
+    mesh.indices.extend_from_slice(&[first, first + 1, first + 2, first + 2, first + 3, first]);
+}
+
+// which of x, y and z a unit direction lies along
+fn axis(direction : [i32; 3]) -> usize {
+    direction.iter().position(|component| *component != 0).unwrap()
+}
+
+fn to_vector([x, y, z] : [i32; 3]) -> Vector3<f32> {
+    Vector3::new(x as f32, y as f32, z as f32)
+}
+
+// turn a vector the way a rotation turns the axes
+fn rotate(rotation : Rotation, vector : Vector3<f32>) -> Vector3<f32> {
+    to_vector(rotation.apply([1, 0, 0])) * vector.x
+        + to_vector(rotation.apply([0, 1, 0])) * vector.y
+        + to_vector(rotation.apply([0, 0, 1])) * vector.z
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use cgmath::InnerSpace;
+    use crate::core::assets::Assets;
+    use crate::creation::atlas_packer::{pack_directory, pack_layers};
+    use crate::creation::block_registry::{load_block_registry, BlockId};
+    use crate::creation::chunk::generate_block;
+    use crate::creation::cube::TerrainBlocks;
+    use crate::creation::noise::Noise;
+    use crate::creation::position::ChunkPos;
+    use crate::game_specs::{ATLAS_OPTIONS, BLOCK_TEXTURE_DIRECTORY};
+    use super::*;
+
+    const SEED : u32 = 1234;
+
+    fn textures() -> BlockTypeConfig {
+        pack_directory(&Assets::built_in(), BLOCK_TEXTURE_DIRECTORY, ATLAS_OPTIONS).unwrap().config
+    }
+
+    fn blocks() -> BlockRegistry {
+        load_block_registry(&Assets::built_in()).unwrap()
+    }
+
+    fn block(name : &str) -> BlockState {
+        BlockState::new(blocks().require(name).unwrap())
+    }
+
+    fn air(_position : BlockPos) -> BlockState {
+        BlockState::AIR
+    }
+
+    fn chunk_with(blocks : &[(usize, usize, usize)]) -> Chunk {
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0, 0));
+        let stone = block("stone");
+        for &(x, y, z) in blocks {
+            chunk.set(LocalPos::new(x, y, z), stone);
+        }
+        chunk
+    }
+
+    fn chunk_of(blocks : &[((usize, usize, usize), BlockState)]) -> Chunk {
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0, 0));
+        for &((x, y, z), block) in blocks {
+            chunk.set(LocalPos::new(x, y, z), block);
+        }
+        chunk
+    }
+
+    fn full_chunk() -> Chunk {
+        let full : Vec<_> = (0..MAX_CHUNK_HEIGHT)
+            .flat_map(|y| (0..CHUNK_SIZE).flat_map(move |z| (0..CHUNK_SIZE).map(move |x| (x, y, z))))
+            .collect();
+        chunk_with(&full)
+    }
+
+    fn triangle_count(mesh : &MeshData) -> usize {
+        mesh.indices.len() / 3
+    }
+
+    // the four vertices of every quad, in the order they were added
+    fn quads(mesh : &MeshData) -> impl Iterator<Item = &[Vertex]> {
+        mesh.vertices.chunks_exact(4)
+    }
+
+    fn area(mesh : &MeshData) -> f32 {
+        quads(mesh)
+            .map(|quad| (quad[1].position - quad[0].position).magnitude() * (quad[3].position - quad[0].position).magnitude())
+            .sum()
+    }
+
+    // every block face a mesh covers, as the face's centre (doubled so it's a whole number), normal and atlas tile
+    fn covered_faces(mesh : &MeshData) -> HashSet<([i32; 3], [i32; 3], [u32; 4])> {
+        let mut faces = HashSet::new();
+
+        for quad in quads(mesh) {
+            let u = quad[1].position - quad[0].position;
+            let v = quad[3].position - quad[0].position;
+            let (width, height) = (u.magnitude().round() as usize, v.magnitude().round() as usize);
+
+            for i in 0..width {
+                for j in 0..height {
+                    let centre = quad[0].position
+                        + u * ((i as f32 + 0.5) / width as f32)
+                        + v * ((j as f32 + 0.5) / height as f32);
+                    let key = (
+                        [centre.x, centre.y, centre.z].map(|c| (c * 2.0).round() as i32),
+                        [quad[0].normal.x, quad[0].normal.y, quad[0].normal.z].map(|c| c as i32),
+                        [quad[0].tile.x, quad[0].tile.y, quad[0].tile.z, quad[0].tile.w].map(f32::to_bits),
+                    );
+
+                    // quads never overlap
+                    assert!(faces.insert(key), "face {:?} is covered twice", key);
+                }
+            }
+        }
+
+        faces
+    }
+
+    // generated terrain chunks along with the terrain around them
+    fn generated_chunks() -> Vec<(Chunk, impl Fn(BlockPos) -> BlockState)> {
+        let terrain = TerrainBlocks::from_registry(&blocks()).unwrap();
+
+        [ChunkPos::new(0, -1, 0), ChunkPos::new(-1, -1, -1), ChunkPos::new(3, -1, -5)]
+            .into_iter()
+            .map(|position| {
+                let noise = Noise::new(SEED);
+                (Chunk::generate(position, SEED, &terrain), move |block| generate_block(&noise, block, &terrain))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn empty_chunk_has_no_faces() {
+        for mode in [MeshMode::Naive, MeshMode::Greedy] {
+            assert!(mesh_chunk(&chunk_with(&[]), mode, &blocks(), &textures(), air).is_empty());
+        }
+    }
+
+    #[test]
+    fn single_block_has_six_faces() {
+        for mode in [MeshMode::Naive, MeshMode::Greedy] {
+            let mesh = mesh_chunk(&chunk_with(&[(4, 4, 4)]), mode, &blocks(), &textures(), air);
+
+            assert_eq!(mesh.vertices.len(), 6 * 4);
+            assert_eq!(triangle_count(&mesh), 6 * 2);
+        }
+    }
+
+    #[test]
+    fn faces_use_their_own_texture() {
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0, 0));
+        chunk.set(LocalPos::new(4, 4, 4), block("grass"));
+        let (blocks, textures) = (blocks(), textures());
+        let mesh = mesh_chunk(&chunk, MeshMode::Naive, &blocks, &textures, air);
+
+        for quad in quads(&mesh) {
+            let face = Face::ALL.into_iter().find(|face| to_vector(face.normal()) == quad[0].normal).unwrap();
+            let (x, y, w, h) = textures.get_uv_rect("grass", face.texture_name());
+
+            assert!(quad.iter().all(|vertex| vertex.tile == Vector4::new(x, y, w, h)));
+        }
+
+        let grass = blocks.get(block("grass").id).unwrap();
+        let tile = |face| face_texture(&textures, grass, 0, face).tile;
+        assert_ne!(tile(Face::Top), tile(Face::Front));
+        assert_ne!(tile(Face::Bottom), tile(Face::Front));
+        assert_eq!(tile(Face::Left), tile(Face::Front));
+    }
+
+    #[test]
+    fn faces_carry_their_texture_array_layer() {
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0, 0));
+        chunk.set(LocalPos::new(4, 4, 4), block("grass"));
+        let textures = pack_layers(&Assets::built_in(), BLOCK_TEXTURE_DIRECTORY).unwrap().config;
+        let mesh = mesh_chunk(&chunk, MeshMode::Greedy, &blocks(), &textures, air);
+
+        for quad in quads(&mesh) {
+            let face = Face::ALL.into_iter().find(|face| to_vector(face.normal()) == quad[0].normal).unwrap();
+            let layer = textures.get_layer("grass", face.texture_name()) as f32;
+
+            assert!(quad.iter().all(|vertex| vertex.layer == layer && vertex.tile == Vector4::new(0.0, 0.0, 1.0, 1.0)));
+        }
+
+        assert_ne!(textures.get_layer("grass", "top"), textures.get_layer("grass", "side"));
+    }
+
+    #[test]
+    fn touching_faces_are_culled() {
+        let mesh = mesh_chunk(&chunk_with(&[(4, 4, 4), (5, 4, 4)]), MeshMode::Naive, &blocks(), &textures(), air);
+
+        assert_eq!(triangle_count(&mesh), 10 * 2);
+    }
+
+    #[test]
+    fn only_blocks_with_an_exposed_face_are_visible() {
+        let chunk = full_chunk();
+
+        let stone = block("stone");
+
+        assert!(visible_blocks(&chunk, &blocks(), |_| stone).is_empty());
+
+        let inside = (CHUNK_SIZE - 2) * (MAX_CHUNK_HEIGHT - 2) * (CHUNK_SIZE - 2);
+        assert_eq!(visible_blocks(&chunk, &blocks(), air).len(), CHUNK_SIZE * MAX_CHUNK_HEIGHT * CHUNK_SIZE - inside);
+    }
+
+    #[test]
+    fn faces_against_neighboring_chunks_are_culled() {
+        let chunk = full_chunk();
+        let stone = block("stone");
+
+        assert!(mesh_chunk(&chunk, MeshMode::Naive, &blocks(), &textures(), |_| stone).is_empty());
+
+        let surface = 2 * (CHUNK_SIZE * CHUNK_SIZE + 2 * CHUNK_SIZE * MAX_CHUNK_HEIGHT);
+        assert_eq!(triangle_count(&mesh_chunk(&chunk, MeshMode::Naive, &blocks(), &textures(), air)), surface * 2);
+    }
+
+    #[test]
+    fn greedy_mesh_of_a_full_chunk_is_one_quad_per_side() {
+        let mesh = mesh_chunk(&full_chunk(), MeshMode::Greedy, &blocks(), &textures(), air);
+
+        assert_eq!(triangle_count(&mesh), 6 * 2);
+        assert_eq!(area(&mesh), area(&mesh_chunk(&full_chunk(), MeshMode::Naive, &blocks(), &textures(), air)));
+    }
+
+    #[test]
+    fn triangles_face_outwards() {
+        for mode in [MeshMode::Naive, MeshMode::Greedy] {
+            let stairs = BlockState { id: block("stone_stairs").id, properties: 1 };
+            let chunk = chunk_of(&[
+                ((0, 0, 0), block("stone")), ((1, 0, 0), block("stone")), ((1, 1, 0), block("stone")),
+                ((3, 0, 0), block("stone_slab")), ((4, 0, 0), stairs),
+            ]);
+            let mesh = mesh_chunk(&chunk, mode, &blocks(), &textures(), air);
+
+            for triangle in mesh.indices.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| &mesh.vertices[triangle[i] as usize]);
+                let winding = (b.position - a.position).cross(c.position - a.position);
+
+                assert!(winding.dot(a.normal) > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn greedy_mesh_covers_the_same_faces_as_naive_mesh() {
+        for (chunk, block_at) in generated_chunks() {
+            let naive = mesh_chunk(&chunk, MeshMode::Naive, &blocks(), &textures(), &block_at);
+            let greedy = mesh_chunk(&chunk, MeshMode::Greedy, &blocks(), &textures(), &block_at);
+
+            assert!(!naive.is_empty());
+            assert_eq!(covered_faces(&greedy), covered_faces(&naive));
+            assert_eq!(area(&greedy), area(&naive));
+            assert!(triangle_count(&greedy) < triangle_count(&naive),
+                    "greedy {} triangles, naive {}", triangle_count(&greedy), triangle_count(&naive));
+        }
+    }
+
+    #[test]
+    fn greedy_texture_coordinates_repeat_once_per_block() {
+        for (chunk, block_at) in generated_chunks() {
+            for quad in quads(&mesh_chunk(&chunk, MeshMode::Greedy, &blocks(), &textures(), &block_at)) {
+                let width = (quad[1].position - quad[0].position).magnitude();
+                let height = (quad[3].position - quad[0].position).magnitude();
+
+                assert_eq!(quad[1].texture_coords - quad[0].texture_coords, Vector2::new(width, 0.0));
+                assert_eq!(quad[3].texture_coords - quad[0].texture_coords, Vector2::new(0.0, -height));
+            }
+        }
+    }
+
+    #[test]
+    fn rotated_blocks_turn_their_faces_and_textures() {
+        // a log made from the stone and dirt textures, lying along x
+        let definitions : Vec<BlockDefinition> = serde_json::from_str(r#"[
+            { "id": 0, "name": "air", "opaque": false },
+            { "id": 1, "name": "log", "textures": { "top": "stone", "bottom": "stone", "side": "dirt" },
+              "properties": [ { "name": "axis", "values": ["y", "x"] } ],
+              "variants": [ { "when": { "axis": "x" }, "rotation": { "x": 90, "y": 90 } } ] }
+        ]"#).unwrap();
+        let (blocks, textures) = (BlockRegistry::new(definitions, HashMap::new()).unwrap(), textures());
+        let tile = |sprite, face| {
+            let (x, y, w, h) = textures.get_uv_rect(sprite, face);
+            Vector4::new(x, y, w, h)
+        };
+
+        let mut chunk = Chunk::new(ChunkPos::new(0, 0, 0));
+        chunk.set(LocalPos::new(4, 4, 4), BlockState { id: BlockId(1), properties: 1 });
+        chunk.set(LocalPos::new(5, 4, 4), BlockState { id: BlockId(1), properties: 1 });
+
+        for mode in [MeshMode::Naive, MeshMode::Greedy] {
+            for quad in quads(&mesh_chunk(&chunk, mode, &blocks, &textures, air)) {
+                let normal = quad[0].normal;
+                // the world direction the top of the texture is at, the way texture v decreases
+                let up = -((quad[1].position - quad[0].position) * (quad[1].texture_coords.y - quad[0].texture_coords.y)
+                    + (quad[3].position - quad[0].position) * (quad[3].texture_coords.y - quad[0].texture_coords.y));
+
+                if normal.x != 0.0 {
+                    // the ends of the log
+                    assert_eq!(quad[0].tile, tile("stone", if normal.x > 0.0 { "top" } else { "bottom" }));
+                } else {
+                    // bark, with the top of the texture pointing along the log
+                    assert_eq!(quad[0].tile, tile("dirt", "side"));
+                    assert_eq!(up.normalize().x.abs(), 1.0, "face {:?} has its texture turned the wrong way", normal);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn only_full_sides_hide_faces() {
+        let (blocks, textures) = (blocks(), textures());
+        let (stone, slab) = (block("stone"), block("stone_slab"));
+        let mesh = |chunk : &Chunk| mesh_chunk(chunk, MeshMode::Naive, &blocks, &textures, air);
+        let has_face_at = |mesh : &MeshData, y : f32, normal_y : f32| {
+            quads(mesh).any(|quad| quad[0].normal.y == normal_y && quad.iter().all(|vertex| vertex.position.y == y))
+        };
+
+        let alone = mesh(&chunk_of(&[((4, 4, 4), slab)]));
+        assert_eq!(triangle_count(&alone), 6 * 2);
+        // the top of the slab is halfway up the block
+        assert!(has_face_at(&alone, 4.0, 1.0) && has_face_at(&alone, 3.5, -1.0));
+
+        // a slab on stone hides the top of the stone and the bottom of the slab
+        let on_stone = mesh(&chunk_of(&[((4, 4, 4), slab), ((4, 3, 4), stone)]));
+        assert_eq!(triangle_count(&on_stone), 10 * 2);
+        assert!(!has_face_at(&on_stone, 3.5, 1.0));
+
+        // but the top of the slab doesn't reach the stone above it, so neither face is hidden
+        let under_stone = mesh(&chunk_of(&[((4, 4, 4), slab), ((4, 5, 4), stone)]));
+        assert_eq!(triangle_count(&under_stone), 12 * 2);
+        assert!(has_face_at(&under_stone, 4.5, -1.0));
+    }
+
+    #[test]
+    fn rotated_models_cover_their_turned_sides() {
+        let (blocks, textures) = (blocks(), textures());
+        let stairs = blocks.require("stone_stairs").unwrap();
+        let definition = blocks.get(stairs).unwrap();
+        let facing_east = BlockState { id: stairs, properties: definition.with_property(0, "facing", "east").unwrap() };
+        let stone = block("stone");
+
+        // the tall back of the stairs faces east, hiding the west face of the stone there
+        let east = mesh_chunk(&chunk_of(&[((4, 4, 4), facing_east), ((5, 4, 4), stone)]), MeshMode::Naive, &blocks, &textures, air);
+        assert!(!quads(&east).any(|quad| quad[0].normal == Vector3::new(-1.0, 0.0, 0.0) && quad[0].position.x == 4.5));
+
+        let west = mesh_chunk(&chunk_of(&[((4, 4, 4), facing_east), ((3, 4, 4), stone)]), MeshMode::Naive, &blocks, &textures, air);
+        assert!(quads(&west).any(|quad| quad[0].normal == Vector3::new(1.0, 0.0, 0.0) && quad[0].position.x == 3.5));
+
+        // the tops of the stairs' two boxes and of the stone
+        assert_eq!(quads(&east).filter(|quad| quad[0].normal.y == 1.0).count(), 3);
+    }
+
+    #[test]
+    fn crosses_are_drawn_from_both_sides_and_never_hidden() {
+        let (blocks, textures) = (blocks(), textures());
+        let stone = block("stone");
+        let tall_grass = block("tall_grass");
+        let plant = chunk_of(&[((4, 4, 4), tall_grass)]);
+
+        let alone = mesh_chunk(&plant, MeshMode::Greedy, &blocks, &textures, air);
+        let surrounded = mesh_chunk(&plant, MeshMode::Greedy, &blocks, &textures, |_| stone);
+
+        for mesh in [alone, surrounded] {
+
+            assert_eq!(triangle_count(&mesh), 4 * 2);
+            let (x, y, w, h) = textures.get_uv_rect("tall_grass", "side");
+            assert!(mesh.vertices.iter().all(|vertex| vertex.tile == Vector4::new(x, y, w, h)));
+
+            // each quad has a twin facing the other way
+            for quad in quads(&mesh) {
+                assert!(quads(&mesh).any(|twin| twin[0].normal == -quad[0].normal));
+            }
+        }
+
+        // the plant doesn't hide anything either
+        let mut chunk = chunk_with(&[(4, 3, 4)]);
+        chunk.set(LocalPos::new(4, 4, 4), tall_grass);
+        assert_eq!(triangle_count(&mesh_chunk(&chunk, MeshMode::Naive, &blocks, &textures, air)), (6 + 4) * 2);
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::path::Path;
+use crate::core::assets::AssetError;
+
+// Prepares GLSL for the compiler. `#include "name.glsl"` is replaced by the contents of that asset, found next to
+// the file including it, and `#define`s given from Rust are added straight after `#version`. A file is only
+// included once per shader, so shared files don't need include guards.
+//
+// The result remembers which file and line each of its lines came from, so line numbers in the driver's info log
+// can be turned back into the lines that were actually written.
+
+#[derive(Debug)]
+pub enum PreprocessError {
+    // the shader itself couldn't be read
+    Read { file : String, error : AssetError },
+    // a directive that can't be followed, with the file and line it's on
+    Directive { file : String, line : usize, message : String },
+}
+
+impl Display for PreprocessError {
+    fn fmt(&self, f : &mut Formatter) -> fmt::Result {
+        match self {
+            PreprocessError::Read { file, error } => write!(f, "couldn't read shader {}: {}", file, error),
+            PreprocessError::Directive { file, line, message } => write!(f, "{}:{}: {}", file, line, message),
+        }
+    }
+}
+
+impl Error for PreprocessError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PreprocessError::Read { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PreprocessedSource {
+    pub code : String,
+    files : Vec<String>,
+    // the index into files and the line number, from 1, of each line of code. None for the injected defines
+    lines : Vec<Option<(usize, usize)>>,
+}
+
+// preprocess a shader, reading it and what it includes with `read`
+pub fn preprocess<R : Fn(&str) -> Result<String, AssetError>>(file : &str,
+                                                              defines : &[&str],
+                                                              read : R) -> Result<PreprocessedSource, PreprocessError> {
+    let mut source = PreprocessedSource {
+        code: String::new(),
+        files: Vec::new(),
+        lines: Vec::new(),
+    };
+
+    let code = read(file).map_err(|error| PreprocessError::Read { file: file.to_string(), error })?;
+    let lines : Vec<_> = code.lines().enumerate().collect();
+    source.files.push(file.to_string());
+
+    // #version has to come before everything but comments, so the defines go after it
+    let version = lines
+        .iter()
+        .position(|(_, line)| line.trim_start().starts_with("#version"))
+        .map_or(0, |index| index + 1);
+
+    for &(index, line) in &lines[..version] {
+        source.push_line(line, Some((0, index + 1)));
+    }
+
+    for define in defines {
+        source.push_line(&format!("#define {}", define), None);
+    }
+
+    let mut included = HashSet::from([file.to_string()]);
+    source.add_lines(0, lines[version..].iter().copied(), &mut included, &read)?;
+
+    Ok(source)
+}
+
+impl PreprocessedSource {
+    // the file and line a line of the code came from, counting from 1
+    pub fn origin(&self, line : usize) -> Option<(&str, usize)> {
+        let (file, line) = (*self.lines.get(line.checked_sub(1)?)?)?;
+        Some((&self.files[file], line))
+    }
+
+    // replace the line numbers in an info log with the file and line they refer to. Drivers write them as
+    // "0:12(3)", "0(12)" or "0:12:", where 0 is the source string and 12 the line
+    pub fn map_log(&self, log : &str) -> String {
+        log.lines().map(|line| self.map_log_line(line)).collect::<Vec<_>>().join("\n")
+    }
+
+    fn map_log_line(&self, line : &str) -> String {
+        for (start, _) in line.match_indices('0') {
+            // only a source number on its own, not the end of a longer number
+            if line[..start].ends_with(|c : char| c.is_ascii_digit()) {
+                continue;
+            }
+
+            let rest = &line[start + 1..];
+            let (digits, closing) = if let Some(rest) = rest.strip_prefix(':') {
+                (rest, "")
+            } else if let Some(rest) = rest.strip_prefix('(') {
+                (rest, ")")
+            } else {
+                continue;
+            };
+
+            let length = digits.find(|c : char| !c.is_ascii_digit()).unwrap_or(digits.len());
+            if length == 0 || !digits[length..].starts_with(closing) {
+                continue;
+            }
+
+            let origin = digits[..length].parse().ok().and_then(|number| self.origin(number));
+            if let Some((file, number)) = origin {
+                let end = start + 2 + length + closing.len();
+                return format!("{}{}:{}{}", &line[..start], file, number, &line[end..]);
+            }
+        }
+
+        line.to_string()
+    }
+
+    fn push_line(&mut self, line : &str, origin : Option<(usize, usize)>) {
+        self.code.push_str(line);
+        self.code.push('\n');
+        self.lines.push(origin);
+    }
+
+    fn add_lines<'a, I, R>(&mut self,
+                           file : usize,
+                           lines : I,
+                           included : &mut HashSet<String>,
+                           read : &R) -> Result<(), PreprocessError>
+        where I : Iterator<Item = (usize, &'a str)>, R : Fn(&str) -> Result<String, AssetError> {
+        for (index, line) in lines {
+            let number = index + 1;
+
+            let include = match line.trim_start().strip_prefix("#include") {
+                Some(include) => include.trim(),
+                None => {
+                    self.push_line(line, Some((file, number)));
+                    continue;
+                }
+            };
+
+            let error = |message : &str| PreprocessError::Directive {
+                file: self.files[file].clone(),
+                line: number,
+                message: message.to_string(),
+            };
+
+            let name = include
+                .strip_prefix('"')
+                .and_then(|include| include.strip_suffix('"'))
+                .ok_or_else(|| error("#include needs a file name in double quotes"))?;
+
+            let path = Path::new(&self.files[file]).with_file_name(name);
+            let path = path.to_str().ok_or_else(|| error("invalid file name"))?.to_string();
+
+            if !included.insert(path.clone()) {
+                continue;
+            }
+
+            let code = read(&path).map_err(|e| error(&e.to_string()))?;
+            if code.lines().any(|line| line.trim_start().starts_with("#version")) {
+                return Err(error(&format!("{} can't be included because it has a #version", path)));
+            }
+
+            self.files.push(path);
+            self.add_lines(self.files.len() - 1, code.lines().enumerate(), included, read)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::*;
+
+    fn files() -> HashMap<&'static str, &'static str> {
+        HashMap::from([
+            ("shaders/block.frag", "#version 330 core\n#include \"common.glsl\"\n#include \"common.glsl\"\nvoid main() {}\n"),
+            ("shaders/common.glsl", "#include \"lighting.glsl\"\nfloat shade;"),
+            ("shaders/lighting.glsl", "vec3 light;\nvec3 ambient;"),
+            ("shaders/versioned.glsl", "#version 330 core"),
+        ])
+    }
+
+    fn read(name : &str) -> Result<String, AssetError> {
+        files().get(name).map(|code| code.to_string()).ok_or_else(|| AssetError::NotFound(name.to_string()))
+    }
+
+    #[test]
+    fn includes_are_spliced_in_once_after_the_defines() {
+        let source = preprocess("shaders/block.frag", &["TEXTURE_ARRAY", "FOG 1"], read).unwrap();
+
+        assert_eq!(
+            source.code,
+            "#version 330 core\n#define TEXTURE_ARRAY\n#define FOG 1\nvec3 light;\nvec3 ambient;\nfloat shade;\nvoid main() {}\n"
+        );
+
+        assert_eq!(source.origin(1), Some(("shaders/block.frag", 1)));
+        assert_eq!(source.origin(2), None);
+        assert_eq!(source.origin(5), Some(("shaders/lighting.glsl", 2)));
+        assert_eq!(source.origin(6), Some(("shaders/common.glsl", 2)));
+        assert_eq!(source.origin(7), Some(("shaders/block.frag", 4)));
+        assert_eq!(source.origin(8), None);
+    }
+
+    #[test]
+    fn defines_go_after_a_version_below_comments() {
+        let commented = |name : &str| match name {
+            "shaders/commented.frag" => Ok("// block faces\n\n#version 330 core\nvoid main() {}".to_string()),
+            "shaders/unversioned.frag" => Ok("void main() {}".to_string()),
+            _ => read(name),
+        };
+
+        let source = preprocess("shaders/commented.frag", &["FOG 1"], commented).unwrap();
+        assert_eq!(source.code, "// block faces\n\n#version 330 core\n#define FOG 1\nvoid main() {}\n");
+        assert_eq!(source.origin(3), Some(("shaders/commented.frag", 3)));
+        assert_eq!(source.origin(4), None);
+        assert_eq!(source.origin(5), Some(("shaders/commented.frag", 4)));
+
+        let source = preprocess("shaders/unversioned.frag", &["FOG 1"], commented).unwrap();
+        assert_eq!(source.code, "#define FOG 1\nvoid main() {}\n");
+    }
+
+    #[test]
+    fn info_logs_point_at_the_original_lines() {
+        let source = preprocess("shaders/block.frag", &["TEXTURE_ARRAY"], read).unwrap();
+
+        assert_eq!(source.map_log("0:4(7): error: syntax error"), "shaders/lighting.glsl:2(7): error: syntax error");
+        assert_eq!(source.map_log("0(6) : error C0000: oops"), "shaders/block.frag:4 : error C0000: oops");
+        assert_eq!(source.map_log("ERROR: 0:5: 'shade' : redefinition\nERROR: 1 compilation errors"),
+                   "ERROR: shaders/common.glsl:2: 'shade' : redefinition\nERROR: 1 compilation errors");
+        assert_eq!(source.map_log("warning: 10 lines"), "warning: 10 lines");
+    }
+
+    #[test]
+    fn bad_includes_say_where_they_are() {
+        let missing = |name : &str| match name {
+            "shaders/main.vert" => Ok("#version 330 core\nvoid f();\n#include \"nothing.glsl\"".to_string()),
+            "shaders/other.vert" => Ok("#include <common.glsl>".to_string()),
+            "shaders/nested.vert" => Ok("#include \"versioned.glsl\"".to_string()),
+            _ => read(name),
+        };
+
+        let error = |file| match preprocess(file, &[], missing) {
+            Err(PreprocessError::Directive { file, line, .. }) => (file, line),
+            _ => panic!("{} should fail to preprocess", file),
+        };
+
+        assert_eq!(error("shaders/main.vert"), ("shaders/main.vert".to_string(), 3));
+        assert_eq!(error("shaders/other.vert"), ("shaders/other.vert".to_string(), 1));
+        assert_eq!(error("shaders/nested.vert"), ("shaders/nested.vert".to_string(), 1));
+        assert!(matches!(preprocess("shaders/none.vert", &[], missing), Err(PreprocessError::Read { .. })));
+    }
+}
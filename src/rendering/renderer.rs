@@ -1,163 +1,390 @@
-use std::ffi::CString;
-use std::mem;
-use cgmath::{Matrix4};
-use gl::types::{GLenum, GLfloat, GLsizei, GLuint};
+use std::collections::HashMap;
+use std::error::Error;
+use cgmath::{Matrix4, SquareMatrix};
+use gl::types::GLenum;
+use image::DynamicImage;
+use crate::core::assets::{AssetError, Assets};
 use crate::core::lib::{polygon_mode};
-use crate::creation::cube::{Cube, CubeType};
-use crate::game_specs::{POLYGON_MODE};
-use crate::rendering::shader::Shader;
-use crate::rendering::texture::Texture;
+use crate::creation::atlas_packer::{pack_directory, pack_layers, PackedTextures};
+use crate::creation::block_config::BlockTypeConfig;
+use crate::creation::chunk::Chunk;
+use crate::creation::chunk_manager::ChunkEvent;
+use crate::creation::block_registry::{load_block_registry, BlockId, BlockRegistry, BlockState};
+use crate::creation::cube::Cube;
+use crate::creation::position::{BlockPos, ChunkPos};
 use crate::creation::world::World;
+use crate::game_specs::{ATLAS_OPTIONS, BLOCK_TEXTURE_DIRECTORY, MESH_MODE, POLYGON_MODE, RENDER_PATH};
+use crate::rendering::gl_object::Buffer;
+use crate::rendering::instancing::{Instance, InstanceBatch, CUBE_FACES};
+use crate::rendering::mesh::Mesh;
+use crate::rendering::mesher::{face_texture, mesh_chunk, mesh_models, visible_blocks, MeshMode};
+use crate::rendering::shader::Shader;
+use crate::rendering::texture::{Texture, TextureBackend, TextureError};
+use crate::rendering::uniform_buffer::{FrameUniforms, UniformBuffer, FRAME_UNIFORMS_BINDING, FRAME_UNIFORMS_BLOCK};
+
+const VERTEX_SHADER : &str = "shaders/shader.vert";
+const FRAGMENT_SHADER : &str = "shaders/shader.frag";
+
+// how the renderer draws the world
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RenderPath {
+    // one mesh of exposed faces per chunk
+    Meshed,
+    // every cube with an exposed face drawn whole, one instanced draw call per block type, blocks that aren't
+    // cubes still use chunk meshes
+    Instanced,
+}
+
+impl RenderPath {
+    // the other path
+    pub fn toggled(self) -> RenderPath {
+        match self {
+            RenderPath::Meshed => RenderPath::Instanced,
+            RenderPath::Instanced => RenderPath::Meshed,
+        }
+    }
+}
 
 pub struct Renderer {
     shader_program : Shader,
-    vao : GLuint,
-    block_atlas: Texture
+    // an atlas or a texture array, see TextureBackend
+    block_atlas: Texture,
+    blocks : BlockRegistry,
+    // where each block's textures are in the atlas or texture array
+    block_textures : BlockTypeConfig,
+    render_path : RenderPath,
+    // the exposed faces of each loaded chunk, or only the blocks that aren't cubes when drawing instanced
+    // chunks with nothing to draw have no mesh
+    chunk_meshes : HashMap<ChunkPos, Mesh>,
+    mesh_mode : MeshMode,
+    // Cube::VERTICES, shared by every instance batch
+    cube_vbo : Buffer,
+    // the visible cubes of each loaded chunk when drawing instanced, along with their block ids
+    chunk_instances : HashMap<ChunkPos, Vec<(BlockId, Instance)>>,
+    // one batch per block id, rebuilt from chunk_instances when they change
+    instance_batches : HashMap<BlockId, InstanceBatch>,
+    instances_changed : bool,
+    // how the block textures are packed, kept for reloading them
+    texture_backend : TextureBackend,
+    // draw calls made by the last frame
+    draw_calls : usize,
+    // the camera's matrices, shared with every program
+    frame_uniforms : UniformBuffer<FrameUniforms>,
 }
 
 impl Renderer {
-    pub fn new() -> Self {
+    pub fn new(assets : &Assets, blocks : BlockRegistry, textures : PackedTextures) -> Result<Self, Box<dyn Error>> {
 
-        let shader_program = Shader::new("shaders/shader.vert", "shaders/shader.frag");
-        let block_atlas = unsafe { Texture::new("resources/textures/spritesheet.png", true) };
+        let shader_program = Shader::new(assets, VERTEX_SHADER, FRAGMENT_SHADER, texture_defines(&textures))?;
+        let texture_backend = texture_backend(&textures);
+        let (block_atlas, block_textures) = unsafe { upload_textures(textures)? };
 
-        Renderer {
+        Ok(Renderer {
             shader_program,
-            vao: 0,
             block_atlas,
-        }
+            blocks,
+            block_textures,
+            render_path: RENDER_PATH,
+            chunk_meshes: HashMap::new(),
+            mesh_mode: MESH_MODE,
+            // the whole world is cubes so instanced drawing can share one vertex buffer
+            cube_vbo: define_buffer(gl::ARRAY_BUFFER, &Cube::VERTICES, gl::STATIC_DRAW),
+            chunk_instances: HashMap::new(),
+            instance_batches: HashMap::new(),
+            instances_changed: false,
+            texture_backend,
+            draw_calls: 0,
+            frame_uniforms: UniformBuffer::new(FRAME_UNIFORMS_BINDING),
+        })
     }
 
-    pub fn init_renderer(&mut self, world : World) {
+    pub fn init_renderer(&mut self) {
         unsafe {
-            gl::UseProgram(self.shader_program.id);
+            gl::UseProgram(self.shader_program.id());
             gl::Enable(gl::DEPTH_TEST);
-            //gl::Enable(gl::CULL_FACE);
-            //gl::CullFace(gl::BACK);
+            // the mesher and Cube::VERTICES wind every face counter-clockwise seen from outside the block
+            gl::Enable(gl::CULL_FACE);
+            gl::CullFace(gl::BACK);
+
+            self.bind_program_and_texture();
+        }
+
+        // "settings"
+        unsafe { gl::ClearColor(0.60, 0.7, 0.9, 1.0); }
+        polygon_mode(POLYGON_MODE);
+    }
 
-            // Generate and bind vertex array object (VAO)
-            gl::GenVertexArrays(1, &mut self.vao);
-            gl::BindVertexArray(self.vao);
+    // load the blocks, textures and shaders again and rebuild every chunk with them, if anything fails to load
+    // the renderer carries on with what it had. The terrain generator keeps the block ids it started with
+    pub fn reload(&mut self, assets : &Assets, world : &World) -> Result<(), Box<dyn Error>> {
+        let blocks = load_block_registry(assets)?;
+        let textures = pack_block_textures(assets, self.texture_backend)?;
 
-            // the whole world is cubes so the vertex and index buffers will be the same for everything
-            let vbo = define_buffer(gl::ARRAY_BUFFER, &Cube::VERTICES, gl::STATIC_DRAW);
-            //let ibo = define_buffer(gl::ELEMENT_ARRAY_BUFFER, &Cube::INDICES, gl::STATIC_DRAW); // TODO index buffer doesn't work, I have the order wrong
+        let shader_program = Shader::new(assets, VERTEX_SHADER, FRAGMENT_SHADER, texture_defines(&textures))?;
+        let (block_atlas, block_textures) = unsafe { upload_textures(textures)? };
 
-            // define attribute pointers
-            let stride = (5 * mem::size_of::<GLfloat>()) as GLsizei;
-            self.define_attrib_pointers(stride);
+        // the old program and texture are deleted as they're replaced
+        self.shader_program = shader_program;
+        self.block_atlas = block_atlas;
+        unsafe { self.bind_program_and_texture(); }
+
+        self.blocks = blocks;
+        self.block_textures = block_textures;
+        self.set_render_path(world, self.render_path);
+
+        Ok(())
+    }
 
-            //assign shader sampler to texture unit
-            self.shader_program.set_int(&CString::new("blockAtlas").unwrap(), 0);
+    unsafe fn bind_program_and_texture(&self) {
+        gl::UseProgram(self.shader_program.id());
 
-            // only ever using one texture
-            gl::ActiveTexture(gl::TEXTURE0);
-            gl::BindTexture(gl::TEXTURE_2D, self.block_atlas.id);
+        //assign shader sampler to texture unit
+        self.shader_program.set("blockAtlas", &0);
+        self.shader_program.bind_uniform_block(FRAME_UNIFORMS_BLOCK, FRAME_UNIFORMS_BINDING);
+
+        // only ever using one texture
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::BindTexture(self.block_atlas.target, self.block_atlas.id());
+    }
+
+    // keep the renderer's copy of the world in step with the chunk manager
+    pub fn handle_chunk_event(&mut self, world : &World, event : ChunkEvent) {
+        match event {
+            ChunkEvent::Loaded(position) | ChunkEvent::Modified(position) => self.rebuild_chunk(world, position),
+            ChunkEvent::Unloaded(position) => self.remove_chunk(position),
         }
+    }
 
-        // "settings"
-        unsafe { gl::ClearColor(0.60, 0.7, 0.9, 1.0); }
-        polygon_mode(POLYGON_MODE);
+    pub fn render_path(&self) -> RenderPath {
+        self.render_path
+    }
+
+    // switch between chunk meshes and instanced cubes, rebuilding every loaded chunk for the new path
+    pub fn set_render_path(&mut self, world : &World, path : RenderPath) {
+        for position in world.chunk_positions() {
+            self.remove_chunk(position);
+        }
+
+        self.render_path = path;
+
+        for position in world.chunk_positions() {
+            self.rebuild_chunk(world, position);
+        }
+    }
+
+    // the blocks as of the last reload
+    pub fn blocks(&self) -> &BlockRegistry {
+        &self.blocks
+    }
+
+    pub fn draw_calls(&self) -> usize {
+        self.draw_calls
+    }
+
+    pub fn mesh_mode(&self) -> MeshMode {
+        self.mesh_mode
+    }
+
+    // switch between naive and greedy meshing, rebuilding every loaded chunk
+    pub fn set_mesh_mode(&mut self, world : &World, mode : MeshMode) {
+        self.mesh_mode = mode;
+
+        if self.render_path == RenderPath::Meshed {
+            for position in world.chunk_positions() {
+                self.rebuild_mesh(world, position);
+            }
+        }
     }
 
     // called from game window loop
-    pub fn render(&mut self, projection : Matrix4<f32>, view : Matrix4<f32>, models : (Vec<Matrix4<f32>>, Vec<&CubeType>)) {
+    pub fn render(&mut self, projection : Matrix4<f32>, view : Matrix4<f32>) {
         // render
         unsafe {
             // clear buffers
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
 
             // pass to the shaders
-            self.shader_program.set_mat4(&CString::new("projection").unwrap(), &projection);
-            self.shader_program.set_mat4(&CString::new("view").unwrap(), &view);
+            self.frame_uniforms.update(&FrameUniforms { projection, view });
+            self.shader_program.set("instanced", &(self.render_path == RenderPath::Instanced));
 
+            self.draw_calls = 0;
 
-            for i in 0..models.0.len() {
-                // only send model to renderer if it isn't air
-                if *models.1[i] != CubeType::AIR {
-                    self.shader_program.set_mat4(&CString::new("model").unwrap(), &models.0[i]);
+            if self.render_path == RenderPath::Instanced {
+                if self.instances_changed {
+                    self.update_instance_batches();
                 }
 
-                // send block type information to fragment shader
-                match models.1[i]  {
-                    CubeType::GRASS => {
-                        self.shader_program.set_int(&CString::new("blockType").unwrap(), 1);
-                    }
-                    CubeType::STONE => {
-                        self.shader_program.set_int(&CString::new("blockType").unwrap(), 2);
-                    }
-                    CubeType::DIRT => {
-                        self.shader_program.set_int(&CString::new("blockType").unwrap(), 3);
-                    }
-                     // we don't want anything to render for air
-                    _ => {}
+                // instances are already in world space
+                self.shader_program.set("model", &Matrix4::identity());
+
+                for batch in self.instance_batches.values().filter(|batch| !batch.is_empty()) {
+                    batch.draw();
+                    self.draw_calls += 1;
                 }
 
-                // draw objects
-                gl::DrawArrays(
-                    gl::TRIANGLES,
-                    0,
-                    36
-                );
-
-                // TODO reorder the index array so this works
-                // gl::DrawElements(
-                //     gl::TRIANGLES,
-                //     36,
-                //     gl::UNSIGNED_INT,
-                //     std::ptr::null(), // Indices are provided by the bound element array buffer
-                // );
+                self.shader_program.set("instanced", &false);
             }
+
+            // mesh vertices are relative to their chunk, so each chunk only needs moving into place
+            for (position, mesh) in &self.chunk_meshes {
+                let model = Matrix4::from_translation(position.origin().to_vec3());
+                self.shader_program.set("model", &model);
+
+                mesh.draw();
+            }
+
+            self.draw_calls += self.chunk_meshes.len();
+
+            gl::BindVertexArray(0);
         }
     }
 
-    unsafe fn define_attrib_pointers(&self, stride : GLsizei) {
-        let pos_attr_location = gl::GetAttribLocation(
-            self.shader_program.id,
-            CString::new("position").unwrap().as_ptr()
-        );
+    fn rebuild_chunk(&mut self, world : &World, position : ChunkPos) {
+        match self.render_path {
+            RenderPath::Meshed => self.rebuild_mesh(world, position),
+            RenderPath::Instanced => self.rebuild_instances(world, position),
+        }
+    }
 
-        let texture_attr_location = gl::GetAttribLocation(
-            self.shader_program.id,
-            CString::new("texture").unwrap().as_ptr()
-        );
+    fn remove_chunk(&mut self, position : ChunkPos) {
+        self.remove_mesh(position);
 
-        // position attribute
-        gl::VertexAttribPointer(
-            pos_attr_location as GLuint,
-            3,
-            gl::FLOAT,
-            gl::FALSE,
-            stride,
-            std::ptr::null(),
-        );
-        gl::EnableVertexAttribArray(pos_attr_location as GLuint);
-
-        // texture attribute
-        gl::VertexAttribPointer(
-            texture_attr_location as GLuint,
-            2,
-            gl::FLOAT,
-            gl::FALSE,
-            stride,
-            (3 * mem::size_of::<GLfloat>()) as *const std::ffi::c_void,
-        );
-        gl::EnableVertexAttribArray(texture_attr_location as GLuint);
+        if self.chunk_instances.remove(&position).is_some() {
+            self.instances_changed = true;
+        }
+    }
+
+    fn rebuild_mesh(&mut self, world : &World, position : ChunkPos) {
+        self.remove_mesh(position);
+
+        if let Some(chunk) = world.chunk(position) {
+            let data = mesh_chunk(chunk, self.mesh_mode, &self.blocks, &self.block_textures, blocks_around(world, chunk));
+
+            if !data.is_empty() {
+                self.chunk_meshes.insert(position, Mesh::new(&data));
+            }
+        }
+    }
+
+    // the mesh's buffers are deleted as it's dropped
+    fn remove_mesh(&mut self, position : ChunkPos) {
+        self.chunk_meshes.remove(&position);
+    }
+
+    fn rebuild_instances(&mut self, world : &World, position : ChunkPos) {
+        self.remove_mesh(position);
+
+        let chunk = match world.chunk(position) {
+            Some(chunk) => chunk,
+            None => return,
+        };
+
+        // blocks that aren't cubes can't be instanced
+        let models = mesh_models(chunk, &self.blocks, &self.block_textures, blocks_around(world, chunk));
+        if !models.is_empty() {
+            self.chunk_meshes.insert(position, Mesh::new(&models));
+        }
+
+        let instances = visible_blocks(chunk, &self.blocks, blocks_around(world, chunk))
+            .into_iter()
+            .map(|(local, state, block)| {
+                let tiles = CUBE_FACES.map(|face| face_texture(&self.block_textures, block, state.properties, face).tile);
+
+                (state.id, Instance {
+                    position: chunk.block_pos(local).to_vec3(),
+                    face_tiles: tiles.map(|tile| tile.rect),
+                    face_layers: tiles.map(|tile| tile.layer),
+                })
+            })
+            .collect();
+
+        self.chunk_instances.insert(position, instances);
+        self.instances_changed = true;
+    }
+
+    // gather every chunk's instances into one batch per block type
+    fn update_instance_batches(&mut self) {
+        let mut by_type : HashMap<BlockId, Vec<Instance>> = HashMap::new();
+
+        for (block_type, instance) in self.chunk_instances.values().flatten() {
+            by_type.entry(*block_type).or_default().push(*instance);
+        }
+
+        for block_type in by_type.keys() {
+            if !self.instance_batches.contains_key(block_type) {
+                self.instance_batches.insert(*block_type, InstanceBatch::new(&self.cube_vbo));
+            }
+        }
+
+        // block types that have disappeared are left with an empty batch
+        for (block_type, batch) in self.instance_batches.iter_mut() {
+            batch.upload(by_type.get(block_type).map_or(&[], Vec::as_slice));
+        }
+
+        self.instances_changed = false;
+    }
+}
+
+// the blocks just outside a chunk, read from its neighbors which are found once rather than for every block.
+// Blocks in chunks that aren't loaded count as air, the chunk is rebuilt when they load
+fn blocks_around<'a>(world : &'a World, chunk : &'a Chunk) -> impl Fn(BlockPos) -> BlockState + 'a {
+    let neighbors : Vec<&Chunk> = world.neighbors(chunk.position).collect();
+
+    move |block| {
+        let position = block.chunk_pos();
+
+        match neighbors.iter().find(|neighbor| neighbor.position == position) {
+            Some(neighbor) => neighbor.get(block.local_pos()),
+            None => world.get_block(block).unwrap_or(BlockState::AIR),
+        }
+    }
+}
+
+// pack the block textures for a texture backend
+pub fn pack_block_textures(assets : &Assets, backend : TextureBackend) -> Result<PackedTextures, AssetError> {
+    match backend {
+        TextureBackend::Atlas => pack_directory(assets, BLOCK_TEXTURE_DIRECTORY, ATLAS_OPTIONS).map(PackedTextures::Atlas),
+        TextureBackend::Array => pack_layers(assets, BLOCK_TEXTURE_DIRECTORY).map(PackedTextures::Layers),
+    }
+}
+
+fn texture_backend(textures : &PackedTextures) -> TextureBackend {
+    match textures {
+        PackedTextures::Atlas(_) => TextureBackend::Atlas,
+        PackedTextures::Layers(_) => TextureBackend::Array,
+    }
+}
+
+// the shader is compiled for whichever kind of texture the blocks come in
+fn texture_defines(textures : &PackedTextures) -> &'static [&'static str] {
+    match textures {
+        PackedTextures::Atlas(_) => &[],
+        PackedTextures::Layers(_) => &["TEXTURE_ARRAY"],
+    }
+}
+
+// give the block textures to the GPU, along with where each block's textures are in them
+unsafe fn upload_textures(textures : PackedTextures) -> Result<(Texture, BlockTypeConfig), TextureError> {
+    match textures {
+        PackedTextures::Atlas(atlas) => {
+            let texture = Texture::new(DynamicImage::ImageRgba8(atlas.image), true, ATLAS_OPTIONS.max_mipmap_level())?;
+            Ok((texture, atlas.config))
+        }
+        PackedTextures::Layers(layers) => Ok((Texture::new_array(&layers.layers)?, layers.config)),
     }
 }
 
 // Generate and bind buffer objects for both VBO and IBO
-fn define_buffer<T>(target: GLenum, array: &[T], draw_type: GLenum) -> GLuint {
-    let mut buffer_object = 0;
+fn define_buffer<T>(target: GLenum, array: &[T], draw_type: GLenum) -> Buffer {
+    let buffer_object = Buffer::new();
     unsafe {
-        gl::GenBuffers(1, &mut buffer_object);
-        gl::BindBuffer(target, buffer_object);
+        gl::BindBuffer(target, buffer_object.id());
         gl::BufferData(
             target,
-            (array.len() * std::mem::size_of::<T>()) as isize,
+            std::mem::size_of_val(array) as isize,
             array.as_ptr() as *const std::ffi::c_void,
             draw_type,
         );
     }
     buffer_object
-}
\ No newline at end of file
+}
@@ -1,147 +1,373 @@
-use std::ffi::{CStr, CString};
-use std::fs::File;
-use std::io::Read;
-use cgmath::{Array, Matrix, Matrix4, Vector4};
-use gl::types::{GLchar, GLenum, GLint, GLuint};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::ffi::CString;
+use std::fmt::{self, Display, Formatter};
+use cgmath::{Array, Matrix, Matrix3, Matrix4, Vector2, Vector3, Vector4};
+use gl::types::{GLchar, GLenum, GLint, GLsizei, GLuint};
+use crate::core::assets::Assets;
+use crate::rendering::gl_object::{GlShader, Program};
+use crate::rendering::preprocessor::{preprocess, PreprocessError, PreprocessedSource};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+}
+
+impl ShaderStage {
+    fn gl_type(self) -> GLenum {
+        match self {
+            ShaderStage::Vertex => gl::VERTEX_SHADER,
+            ShaderStage::Fragment => gl::FRAGMENT_SHADER,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ShaderError {
+    // the source or one of its includes couldn't be read
+    Preprocess(PreprocessError),
+    // the driver's info log says what's wrong
+    Compile { stage : ShaderStage, file : String, log : String },
+    Link { log : String },
+}
+
+impl Display for ShaderError {
+    fn fmt(&self, f : &mut Formatter) -> fmt::Result {
+        match self {
+            ShaderError::Preprocess(error) => error.fmt(f),
+            ShaderError::Compile { stage, file, log } => write!(f, "{:?} shader {} didn't compile:\n{}", stage, file, log),
+            ShaderError::Link { log } => write!(f, "shaders didn't link:\n{}", log),
+        }
+    }
+}
+
+impl Error for ShaderError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ShaderError::Preprocess(error) => Some(error),
+            _ => None,
+        }
+    }
+}
 
 pub struct Shader {
-    //program id
-    pub id: u32
+    // deleted when the shader is dropped
+    program : Program,
+    // looked up once after linking, uniforms are set by name
+    uniforms : HashMap<String, GLint>,
+    // names that have been set without being a uniform, so each is only warned about once
+    unknown_uniforms : RefCell<HashSet<String>>,
 }
 
 impl Shader {
-    pub fn new(vertex_file_path : &str, fragment_file_path : &str) -> Shader {
+    // the shaders are asset names, see core::assets, and are run through rendering::preprocessor first with
+    // `#define NAME` added to both for each of the defines
+    pub fn new(assets : &Assets,
+               vertex_file_path : &str,
+               fragment_file_path : &str,
+               defines : &[&str]) -> Result<Shader, ShaderError> {
+
+        let vertex_shader = shader_code_from_file(assets, vertex_file_path, defines)?;
+        let fragment_shader = shader_code_from_file(assets, fragment_file_path, defines)?;
+
+        // Compile and link shaders, the stages are deleted once they're part of the program
+        let vertex_shader = compile_shader(&vertex_shader, ShaderStage::Vertex, vertex_file_path)?;
+        let fragment_shader = compile_shader(&fragment_shader, ShaderStage::Fragment, fragment_file_path)?;
+
+        let program = create_shader_program(&vertex_shader, &fragment_shader)?;
+
+        Ok(Shader {
+            uniforms: unsafe { active_uniforms(program.id()) },
+            program,
+            unknown_uniforms: RefCell::new(HashSet::new()),
+        })
+    }
+
+    // the program id
+    pub fn id(&self) -> GLuint {
+        self.program.id()
+    }
 
-        let mut shader_program = Shader { id: 0};
+    // attach one of the program's uniform blocks to a binding point, see rendering::uniform_buffer
+    pub unsafe fn bind_uniform_block(&self, name : &str, binding : GLuint) {
+        let block_name = CString::new(name).unwrap();
+        let index = gl::GetUniformBlockIndex(self.id(), block_name.as_ptr());
 
-        let vertex_shader = shader_code_from_file(vertex_file_path);
-        let fragment_shader = shader_code_from_file(fragment_file_path);
+        if index == gl::INVALID_INDEX {
+            self.warn_unknown(name);
+        } else {
+            gl::UniformBlockBinding(self.id(), index, binding);
+        }
+    }
 
-        // Compile and link shaders
-        let vertex_shader = compile_shader(vertex_shader, gl::VERTEX_SHADER);
-        let fragment_shader = compile_shader(fragment_shader, gl::FRAGMENT_SHADER);
-        shader_program.id = create_shader_program(vertex_shader, fragment_shader);
+    // set a uniform by name to any value with a Uniform impl: bool, i32, f32, vectors, 3x3 and 4x4 matrices, or
+    // slices of floats, vectors and matrices for arrays
+    pub unsafe fn set<U : Uniform + ?Sized>(&self, name : &str, value : &U) {
+        let location = match self.location(name) {
+            Some(location) => location,
+            None => return,
+        };
+
+        match value.value() {
+            UniformValue::Int(value) => gl::Uniform1i(location, value),
+            UniformValue::Float(values) => gl::Uniform1fv(location, values.len() as GLsizei, values.as_ptr()),
+            UniformValue::Vec2(values) => gl::Uniform2fv(location, (values.len() / 2) as GLsizei, values.as_ptr()),
+            UniformValue::Vec3(values) => gl::Uniform3fv(location, (values.len() / 3) as GLsizei, values.as_ptr()),
+            UniformValue::Vec4(values) => gl::Uniform4fv(location, (values.len() / 4) as GLsizei, values.as_ptr()),
+            UniformValue::Mat3(values) => gl::UniformMatrix3fv(location, (values.len() / 9) as GLsizei, gl::FALSE, values.as_ptr()),
+            UniformValue::Mat4(values) => gl::UniformMatrix4fv(location, (values.len() / 16) as GLsizei, gl::FALSE, values.as_ptr()),
+        }
+    }
 
-        shader_program
+    // the cached location of an active uniform, None (with a warning the first time) if there isn't one. Uniforms
+    // the compiler found were never used aren't active either
+    fn location(&self, name : &str) -> Option<GLint> {
+        let location = self.uniforms.get(name).copied();
+
+        if location.is_none() {
+            self.warn_unknown(name);
+        }
+
+        location
     }
 
-    pub unsafe fn set_bool(&self, name: &CStr, value: bool) {
-        gl::Uniform1i(
-            gl::GetUniformLocation(self.id, name.as_ptr()),
-            value as i32
-        );
+    fn warn_unknown(&self, name : &str) {
+        if self.unknown_uniforms.borrow_mut().insert(name.to_string()) {
+            eprintln!("Warning: shader has no active uniform called {}", name);
+        }
     }
-    pub unsafe fn set_int(&self, name: &CStr, value: i32) {
-        gl::Uniform1i(gl::GetUniformLocation(
-            self.id,
-            name.as_ptr()
-        ), value);
+}
+
+// the location of every uniform in a linked program, by name
+unsafe fn active_uniforms(program : GLuint) -> HashMap<String, GLint> {
+    let (mut count, mut max_length) = (0, 0);
+    gl::GetProgramiv(program, gl::ACTIVE_UNIFORMS, &mut count);
+    gl::GetProgramiv(program, gl::ACTIVE_UNIFORM_MAX_LENGTH, &mut max_length);
+
+    let mut uniforms = HashMap::new();
+
+    for index in 0..count {
+        let mut name = vec![0u8; max_length.max(1) as usize];
+        let (mut length, mut size, mut kind) = (0, 0, 0);
+        gl::GetActiveUniform(program, index as GLuint, max_length, &mut length, &mut size, &mut kind, name.as_mut_ptr() as *mut GLchar);
+
+        let name = String::from_utf8_lossy(&name[..length as usize]).to_string();
+        let location = gl::GetUniformLocation(program, CString::new(name.as_str()).unwrap().as_ptr());
+
+        // members of uniform blocks have no location of their own
+        if location >= 0 {
+            uniforms.insert(uniform_name(&name).to_string(), location);
+        }
     }
-    pub unsafe fn set_float(&self, name: &CStr, value: f32) {
-        gl::Uniform1f(
-            gl::GetUniformLocation(self.id, name.as_ptr()),
-            value
-        );
+
+    uniforms
+}
+
+// what Shader::set hands to GL: an int, or the floats of one or more values of a type, matrices by column
+#[derive(Debug, PartialEq)]
+pub enum UniformValue<'a> {
+    Int(i32),
+    Float(&'a [f32]),
+    Vec2(&'a [f32]),
+    Vec3(&'a [f32]),
+    Vec4(&'a [f32]),
+    Mat3(&'a [f32]),
+    Mat4(&'a [f32]),
+}
+
+// a value that can be given to a uniform, slices set arrays from their first element
+pub trait Uniform {
+    fn value(&self) -> UniformValue<'_>;
+}
+
+impl Uniform for bool {
+    fn value(&self) -> UniformValue<'_> {
+        UniformValue::Int(*self as i32)
     }
+}
 
-    pub unsafe fn set_mat4(&self, name: &CStr, matrix : &Matrix4<f32>) {
-        let location = gl::GetUniformLocation(
-            self.id,
-            name.as_ptr()
-        );
+impl Uniform for i32 {
+    fn value(&self) -> UniformValue<'_> {
+        UniformValue::Int(*self)
+    }
+}
 
-        gl::UniformMatrix4fv(
-            location,
-            1,
-            gl::FALSE,
-            matrix.as_ptr()
-        );
+impl Uniform for f32 {
+    fn value(&self) -> UniformValue<'_> {
+        UniformValue::Float(std::slice::from_ref(self))
     }
 }
 
-fn shader_code_from_file(file_path : &str) -> CString {
+impl Uniform for [f32] {
+    fn value(&self) -> UniformValue<'_> {
+        UniformValue::Float(self)
+    }
+}
 
-    let mut file = File::open(file_path)
-        .unwrap_or_else(|_| panic!("Failed to open {}", file_path));
+// cgmath's vectors and matrices are their floats in order, so a slice of them is one long slice of floats
+macro_rules! float_uniform {
+    ($type:ty, $variant:ident, $floats:expr) => {
+        impl Uniform for $type {
+            fn value(&self) -> UniformValue<'_> {
+                UniformValue::$variant(unsafe { std::slice::from_raw_parts(self.as_ptr(), $floats) })
+            }
+        }
 
-    let mut code = String::new();
+        impl Uniform for [$type] {
+            fn value(&self) -> UniformValue<'_> {
+                UniformValue::$variant(unsafe { std::slice::from_raw_parts(self.as_ptr() as *const f32, self.len() * $floats) })
+            }
+        }
+    };
+}
 
-    file
-        .read_to_string(&mut code)
-        .expect("failed to read shader");
+float_uniform!(Vector2<f32>, Vec2, 2);
+float_uniform!(Vector3<f32>, Vec3, 3);
+float_uniform!(Vector4<f32>, Vec4, 4);
+float_uniform!(Matrix3<f32>, Mat3, 9);
+float_uniform!(Matrix4<f32>, Mat4, 16);
 
-    let code = CString::new(code.as_bytes()).unwrap();
+// drivers list arrays as "name[0]"
+fn uniform_name(active_name : &str) -> &str {
+    active_name.strip_suffix("[0]").unwrap_or(active_name)
+}
 
-    code
+// the shader's code with its includes and defines added
+fn shader_code_from_file(assets : &Assets, file_path : &str, defines : &[&str]) -> Result<PreprocessedSource, ShaderError> {
+    preprocess(file_path, defines, |name| assets.read_to_string(name)).map_err(ShaderError::Preprocess)
 }
 
-fn compile_shader(source: CString, shader_type: GLenum) -> GLuint {
+fn compile_shader(source: &PreprocessedSource, stage: ShaderStage, file: &str) -> Result<GlShader, ShaderError> {
+    let code = CString::new(source.code.as_bytes())
+        .map_err(|_| ShaderError::Compile { stage, file: file.to_string(), log: "contains a nul byte".to_string() })?;
+
     unsafe {
         // Create a new shader object
-        let shader = gl::CreateShader(shader_type);
+        let shader = GlShader::new(stage.gl_type());
 
         // Set the shader source and compile it
-        gl::ShaderSource(shader, 1, &source.as_ptr(), std::ptr::null());
-        gl::CompileShader(shader);
+        gl::ShaderSource(shader.id(), 1, &code.as_ptr(), std::ptr::null());
+        gl::CompileShader(shader.id());
 
         // Check for compilation errors
         let mut success = gl::FALSE as GLint;
-        gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
+        gl::GetShaderiv(shader.id(), gl::COMPILE_STATUS, &mut success);
 
         if success != gl::TRUE as GLint {
             // Compilation failed, get the error message
             let mut log_length = 0;
-            gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut log_length);
-
-            let mut log = Vec::with_capacity(log_length as usize);
-            log.set_len(log_length as usize - 1); // Subtract 1 to ignore the null terminator
+            gl::GetShaderiv(shader.id(), gl::INFO_LOG_LENGTH, &mut log_length);
 
-            gl::GetShaderInfoLog(shader, log_length, std::ptr::null_mut(), log.as_mut_ptr() as *mut GLchar);
+            let mut log = vec![0u8; log_length.max(1) as usize];
+            gl::GetShaderInfoLog(shader.id(), log_length, std::ptr::null_mut(), log.as_mut_ptr() as *mut GLchar);
 
-            let error_message = String::from_utf8_lossy(&log);
-            println!("Shader compilation error: {}", error_message);
+            // point the errors at the files that were included rather than the combined code
+            return Err(ShaderError::Compile { stage, file: file.to_string(), log: source.map_log(&info_log(&log)) });
         }
 
-        shader
+        Ok(shader)
     }
 }
 
-fn create_shader_program(vertex_shader: GLuint, fragment_shader: GLuint) -> GLuint {
+fn create_shader_program(vertex_shader: &GlShader, fragment_shader: &GlShader) -> Result<Program, ShaderError> {
     unsafe {
         // Create a new shader program
-        let shader_program = gl::CreateProgram();
+        let shader_program = Program::new();
 
         // Attach the vertex and fragment shaders to the program
-        gl::AttachShader(shader_program, vertex_shader);
-        gl::AttachShader(shader_program, fragment_shader);
+        gl::AttachShader(shader_program.id(), vertex_shader.id());
+        gl::AttachShader(shader_program.id(), fragment_shader.id());
 
         // Link the shader program
-        gl::LinkProgram(shader_program);
+        gl::LinkProgram(shader_program.id());
+
+        // Detach the individual shaders since they are now part of the program
+        gl::DetachShader(shader_program.id(), vertex_shader.id());
+        gl::DetachShader(shader_program.id(), fragment_shader.id());
 
         // Check for linking errors
         let mut success = gl::FALSE as GLint;
-        gl::GetProgramiv(shader_program, gl::LINK_STATUS, &mut success);
+        gl::GetProgramiv(shader_program.id(), gl::LINK_STATUS, &mut success);
 
         if success != gl::TRUE as GLint {
             // Linking failed, get the error message
             let mut log_length = 0;
-            gl::GetProgramiv(shader_program, gl::INFO_LOG_LENGTH, &mut log_length);
+            gl::GetProgramiv(shader_program.id(), gl::INFO_LOG_LENGTH, &mut log_length);
+
+            let mut log = vec![0u8; log_length.max(1) as usize];
+            gl::GetProgramInfoLog(shader_program.id(), log_length, std::ptr::null_mut(), log.as_mut_ptr() as *mut GLchar);
+
+            return Err(ShaderError::Link { log: info_log(&log) });
+        }
+
+        Ok(shader_program)
+    }
+}
+
+// the text of an info log, without the nul terminator or trailing newlines
+fn info_log(log : &[u8]) -> String {
+    let end = log.iter().position(|byte| *byte == 0).unwrap_or(log.len());
+    String::from_utf8_lossy(&log[..end]).trim_end().to_string()
+}
 
-            let mut log = Vec::with_capacity(log_length as usize);
-            log.set_len(log_length as usize - 1); // Subtract 1 to ignore the null terminator
+#[cfg(test)]
+mod tests {
+    use crate::core::assets::AssetError;
+    use super::*;
 
-            gl::GetProgramInfoLog(shader_program, log_length, std::ptr::null_mut(), log.as_mut_ptr() as *mut GLchar);
+    #[test]
+    fn missing_shaders_are_read_errors() {
+        let error = shader_code_from_file(&Assets::built_in(), "shaders/missing.vert", &[]).unwrap_err();
 
-            let error_message = String::from_utf8_lossy(&log);
-            println!("Shader program linking error: {}", error_message);
+        assert!(matches!(error, ShaderError::Preprocess(PreprocessError::Read { error: AssetError::NotFound(_), .. })));
+        assert!(error.to_string().contains("shaders/missing.vert"));
+    }
+
+    #[test]
+    fn built_in_shaders_preprocess() {
+        for file in ["shaders/shader.vert", "shaders/shader.frag"] {
+            for defines in [&[][..], &["TEXTURE_ARRAY"][..]] {
+                let source = shader_code_from_file(&Assets::built_in(), file, defines).unwrap();
+                assert!(source.code.starts_with("#version"));
+                assert!(!source.code.contains("#include"));
+            }
+        }
+    }
+
+    #[test]
+    fn arrays_are_set_without_their_index() {
+        assert_eq!(uniform_name("faceTiles[0]"), "faceTiles");
+        assert_eq!(uniform_name("model"), "model");
+
+        let tiles = [Vector4::new(0.0, 0.25, 0.5, 0.75), Vector4::new(1.0, 2.0, 3.0, 4.0)];
+        assert_eq!(tiles[..].value(), UniformValue::Vec4(&[0.0, 0.25, 0.5, 0.75, 1.0, 2.0, 3.0, 4.0]));
+        assert_eq!([0.5f32, 1.5][..].value(), UniformValue::Float(&[0.5, 1.5]));
+        let models = [Matrix4::from_scale(2.0), Matrix4::from_scale(3.0)];
+        match models[..].value() {
+            UniformValue::Mat4(floats) => assert_eq!((floats.len(), floats[0], floats[16], floats[31]), (32, 2.0, 3.0, 1.0)),
+            value => panic!("matrices gave {:?}", value),
         }
+    }
 
-        // Detach and delete the individual shaders since they are now part of the program
-        gl::DetachShader(shader_program, vertex_shader);
-        gl::DetachShader(shader_program, fragment_shader);
-        gl::DeleteShader(vertex_shader);
-        gl::DeleteShader(fragment_shader);
+    #[test]
+    fn uniforms_hand_gl_their_floats_in_order() {
+        assert_eq!(true.value(), UniformValue::Int(1));
+        assert_eq!(3.value(), UniformValue::Int(3));
+        assert_eq!(0.5f32.value(), UniformValue::Float(&[0.5]));
+        assert_eq!(Vector2::new(1.0, 2.0).value(), UniformValue::Vec2(&[1.0, 2.0]));
+        assert_eq!(Vector3::new(1.0, 2.0, 3.0).value(), UniformValue::Vec3(&[1.0, 2.0, 3.0]));
+
+        // matrices go column by column
+        let matrix = Matrix3::new(1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0);
+        assert_eq!(matrix.value(), UniformValue::Mat3(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0]));
+        assert_eq!(Matrix4::from_translation(Vector3::new(5.0, 6.0, 7.0)).value(),
+                   UniformValue::Mat4(&[1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 5.0, 6.0, 7.0, 1.0]));
+    }
 
-        shader_program
+    #[test]
+    fn info_logs_stop_at_the_nul() {
+        assert_eq!(info_log(b"0:3(1): error: syntax error\n\0\0"), "0:3(1): error: syntax error");
     }
-}
\ No newline at end of file
+}
@@ -1,51 +1,144 @@
-use std::path::Path;
-use gl::types::{GLenum, GLuint};
-use image::{DynamicImage, GenericImage};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use gl::types::{GLenum, GLint, GLuint};
+use image::{DynamicImage, GenericImage, RgbaImage};
+use crate::rendering::gl_object::GlTexture;
 
+// deleted when it's dropped
 pub struct Texture {
-    pub id : GLuint
+    texture : GlTexture,
+    // TEXTURE_2D, or TEXTURE_2D_ARRAY for textures made with new_array
+    pub target : GLenum,
 }
 
-impl Texture {
-    pub unsafe fn new(image_path : &str, is_transparent : bool) -> Self {
-        let image = image::open(
-            &Path::new(image_path))
-            .expect("Failed to load texture"
-            );
+#[derive(Debug)]
+pub enum TextureError {
+    // there were no pixels to upload
+    Empty,
+    // more pixels or layers than the GPU supports
+    TooLarge { width : u32, height : u32, layers : u32 },
+}
+
+impl Display for TextureError {
+    fn fmt(&self, f : &mut Formatter) -> fmt::Result {
+        match self {
+            TextureError::Empty => write!(f, "texture has no pixels"),
+            TextureError::TooLarge { width, height, layers } => {
+                write!(f, "{} x {} texture with {} layers is larger than the GPU supports", width, height, layers)
+            }
+        }
+    }
+}
 
-        let id;
+impl Error for TextureError {}
+
+// how block textures are given to the shader, picked with --texture-backend
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextureBackend {
+    // every texture packed into one image, faces carry their rectangle in it
+    Atlas,
+    // one texture per layer of a texture array, faces carry their layer
+    Array,
+}
 
-        if is_transparent {
-            id = load_texture(image, gl::RGBA);
+impl Texture {
+    // mipmaps smaller than max_mipmap_level are never sampled
+    pub unsafe fn new(image : DynamicImage, is_transparent : bool, max_mipmap_level : u32) -> Result<Self, TextureError> {
+        check_size(image.width(), image.height(), 1)?;
+
+        // transparent textures keep their alpha on the GPU, plants and other cut-out blocks need it
+        let texture = if is_transparent {
+            load_texture(image, gl::RGBA, gl::RGBA8, max_mipmap_level)
         } else {
-            id = load_texture(image, gl::RGB);
-        }
+            load_texture(image, gl::RGB, gl::RGB8, max_mipmap_level)
+        };
+
+        Ok(Texture {
+            texture,
+            target: gl::TEXTURE_2D,
+        })
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.texture.id()
+    }
 
-        Texture {
-            id
+    // a texture array with one image per layer, the images must all be the same size
+    pub unsafe fn new_array(layers : &[RgbaImage]) -> Result<Self, TextureError> {
+        let (width, height) = layers.first().map_or((0, 0), |layer| layer.dimensions());
+        check_size(width, height, layers.len() as u32)?;
+
+        let mut data = Vec::with_capacity((width * height * 4) as usize * layers.len());
+        for layer in layers {
+            data.extend_from_slice(layer);
         }
+
+        let texture = GlTexture::new();
+        gl::BindTexture(gl::TEXTURE_2D_ARRAY, texture.id());
+
+        gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
+        gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
+        gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+
+        gl::TexImage3D(gl::TEXTURE_2D_ARRAY,
+                       0,
+                       gl::RGBA8 as i32,
+                       width as i32,
+                       height as i32,
+                       layers.len() as i32,
+                       0,
+                       gl::RGBA,
+                       gl::UNSIGNED_BYTE,
+                       data.as_ptr() as *const std::ffi::c_void);
+
+        // each layer gets its own mipmaps, so they never bleed into each other and need no padding
+        gl::GenerateMipmap(gl::TEXTURE_2D_ARRAY);
+
+        Ok(Texture {
+            texture,
+            target: gl::TEXTURE_2D_ARRAY,
+        })
+    }
+}
+
+unsafe fn check_size(width : u32, height : u32, layers : u32) -> Result<(), TextureError> {
+    if width == 0 || height == 0 || layers == 0 {
+        return Err(TextureError::Empty);
     }
+
+    let (mut max_size, mut max_layers) : (GLint, GLint) = (0, 0);
+    gl::GetIntegerv(gl::MAX_TEXTURE_SIZE, &mut max_size);
+    gl::GetIntegerv(gl::MAX_ARRAY_TEXTURE_LAYERS, &mut max_layers);
+
+    let too_large = width > max_size as u32 || height > max_size as u32 || (layers > 1 && layers > max_layers as u32);
+    if too_large {
+        return Err(TextureError::TooLarge { width, height, layers });
+    }
+
+    Ok(())
 }
-unsafe fn load_texture(mut img: DynamicImage, format : GLenum) -> GLuint {
+
+unsafe fn load_texture(img: DynamicImage, format : GLenum, internal_format : GLenum, max_mipmap_level : u32) -> GlTexture {
     //borrowed directly from : https://github.com/bwasty/learn-opengl-rs/blob/master/src/_1_getting_started/_4_1_textures.rs
-    let mut texture = 0;
+    let texture = GlTexture::new();
 
-    gl::GenTextures(1, &mut texture);
     // all upcoming GL_TEXTURE_2D operations now have effect on this texture object
-    gl::BindTexture(gl::TEXTURE_2D, texture);
+    gl::BindTexture(gl::TEXTURE_2D, texture.id());
 
     // set the texture wrapping parameters
     gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32); // set texture wrapping to gl::REPEAT (default wrapping method)
     gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
 
     // set texture filtering parameters
-    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR as i32);
     gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, max_mipmap_level as i32);
 
     let data = img.raw_pixels();
     gl::TexImage2D(gl::TEXTURE_2D,
                    0,
-                   gl::RGB as i32,
+                   internal_format as i32,
                    img.width() as i32,
                    img.height() as i32,
                    0,
@@ -0,0 +1,65 @@
+use std::ffi::c_void;
+use std::marker::PhantomData;
+use std::mem::size_of;
+use cgmath::Matrix4;
+use gl::types::GLuint;
+use crate::rendering::gl_object::Buffer;
+
+// A uniform buffer holds values shared by every program that uses its block, so they're uploaded once per frame
+// rather than once per program. Each buffer stays attached to its binding point, and programs attach their block to
+// the same point with Shader::bind_uniform_block. The Rust struct has to match the block's std140 layout.
+
+// binding point of the Frame block in frame.glsl
+pub const FRAME_UNIFORMS_BINDING : GLuint = 0;
+pub const FRAME_UNIFORMS_BLOCK : &str = "Frame";
+
+// the Frame block in frame.glsl, matrices are laid out the same way in std140 and in cgmath
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FrameUniforms {
+    pub projection : Matrix4<f32>,
+    pub view : Matrix4<f32>,
+}
+
+pub struct UniformBuffer<T> {
+    buffer : Buffer,
+    data : PhantomData<T>,
+}
+
+impl<T : Copy> UniformBuffer<T> {
+    // a buffer big enough for one T, attached to the binding point
+    pub fn new(binding : GLuint) -> Self {
+        let buffer = Buffer::new();
+
+        unsafe {
+            gl::BindBuffer(gl::UNIFORM_BUFFER, buffer.id());
+            gl::BufferData(gl::UNIFORM_BUFFER, size_of::<T>() as isize, std::ptr::null(), gl::DYNAMIC_DRAW);
+            gl::BindBufferBase(gl::UNIFORM_BUFFER, binding, buffer.id());
+        }
+
+        UniformBuffer {
+            buffer,
+            data: PhantomData,
+        }
+    }
+
+    pub fn update(&self, value : &T) {
+        unsafe {
+            gl::BindBuffer(gl::UNIFORM_BUFFER, self.buffer.id());
+            gl::BufferSubData(gl::UNIFORM_BUFFER, 0, size_of::<T>() as isize, value as *const T as *const c_void);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::offset_of;
+    use super::*;
+
+    #[test]
+    fn frame_uniforms_match_the_std140_layout() {
+        // a mat4 is four vec4 columns, 64 bytes
+        assert_eq!(offset_of!(FrameUniforms, view), 64);
+        assert_eq!(size_of::<FrameUniforms>(), 128);
+    }
+}